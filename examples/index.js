@@ -2,7 +2,7 @@ import * as dev from 'dev'
 
 let build = {
   version: dev.getVersion(),
-  dir: dev.getWorkDir(),
+  dir: await dev.getWorkDir(),
   environment: { "test": "test" },
   steps: [
     "npm install",
@@ -1,4 +1,4 @@
--- local dev = require 'dev'
+local dev = require 'dev'
 M = {}
 
 M.init = function()
@@ -6,3 +6,6 @@ build = {
     'steps': [],
     'environment': {},
 }
+
+for repo in dev.get_repos():
+    build['steps'].append(f"fetch {repo['name']}")
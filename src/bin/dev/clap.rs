@@ -4,7 +4,7 @@ use std::{
     path::PathBuf,
 };
 
-use dev_cli::config::Config;
+use dev_cli::{config::Config, runners::run_alias};
 use env_logger::Target;
 use log::LevelFilter;
 
@@ -12,11 +12,12 @@ use clap::{Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 
 use crate::{
+    config::Settings,
     git::Git,
     github::Github,
     init::Init,
     repo::{Repo, Repos},
-    run::{run_alias, Run},
+    run::Run,
     scan::Scan,
     shell::Shell,
     yaml::Yaml,
@@ -32,6 +33,10 @@ struct Cli {
     #[clap(short, long, value_parser, value_name = "FILE")]
     config: Option<PathBuf>,
 
+    /// Fetch git includes again instead of using their cached checkout
+    #[clap(long)]
+    refresh: bool,
+
     #[clap(subcommand)]
     command: Option<Commands>,
 
@@ -46,6 +51,8 @@ pub trait Command {
 enum Commands {
     Init(Init),
     #[clap(subcommand)]
+    Config(Settings),
+    #[clap(subcommand)]
     Git(Git),
     Github(Github),
     Scan(Scan),
@@ -63,16 +70,12 @@ pub async fn init() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
     log(cli.verbose.log_level_filter())?;
 
-    let config_path: PathBuf = match cli.config {
-        Some(path) => path,
-        None => PathBuf::new().join("dev.toml"),
-    };
-
-    let mut config = Config::load(config_path)?;
+    let mut config = Config::load(cli.config, cli.refresh)?;
     let cfg = config.borrow_mut();
     if let Some(cmd) = cli.command {
         match cmd {
             Commands::Init(cmd) => cmd.run(cfg).await?,
+            Commands::Config(cmd) => cmd.run(cfg).await?,
             Commands::Git(cmd) => cmd.run(cfg).await?,
             Commands::Github(cmd) => cmd.run(cfg).await?,
             Commands::Scan(cmd) => cmd.run(cfg).await?,
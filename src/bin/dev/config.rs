@@ -0,0 +1,39 @@
+use clap::Subcommand;
+
+use crate::clap::Command;
+use dev_cli::config::Config;
+
+#[derive(Subcommand)]
+#[command(arg_required_else_help = true)]
+pub enum Settings {
+    /// Print every value in the effective config
+    Show {
+        /// Include the file or environment variable each value came from
+        #[clap(long)]
+        origin: bool,
+    },
+}
+
+impl Command for Settings {
+    async fn run(&self, config: &mut Config) -> Result<(), anyhow::Error> {
+        match self {
+            Settings::Show { origin } => {
+                for (key, value) in config.values()? {
+                    // One line per value, even for multiline strings
+                    let value = match value {
+                        toml::Value::String(s) => serde_json::to_string(&s)?,
+                        value => value.to_string(),
+                    };
+                    match origin {
+                        true => {
+                            let from = config.origin(&key).unwrap_or("default");
+                            println!("{key} = {value}  # {from}");
+                        }
+                        false => println!("{key} = {value}"),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
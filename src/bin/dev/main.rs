@@ -1,15 +1,19 @@
 use std::process::exit;
+use dev_cli::runners::RunError;
 use log::error;
 
 #[tokio::main]
 async fn main() {
     if let Err(e) = clap::init().await {
         error!("{:?}", e);
-        exit(1);
+        // Scripts that fail with an exit code pass it on
+        let code = e.downcast_ref::<RunError>().and_then(|e| e.exit_code);
+        exit(code.filter(|code| *code != 0).unwrap_or(1));
     }
 }
 
 mod clap;
+mod config;
 mod git;
 mod github;
 mod init;
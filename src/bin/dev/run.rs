@@ -1,9 +1,8 @@
 use crate::clap::Command;
 use anyhow::anyhow;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use dev_cli::config::Config;
-use dev_cli::runners::{Dev, Language, LanguageFunctions};
-use dev_cli::utils::write_tmp_file;
+use dev_cli::runners::{run_alias, Dev, Language, LanguageFunctions, RunStatus};
 use log::debug;
 
 #[derive(Args)]
@@ -22,6 +21,33 @@ pub struct Run {
     pub name: Option<String>,
     #[arg(short, long, help = "arguments to pass to command")]
     pub args: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = Output::Text,
+        help = "how to print what the script returns"
+    )]
+    pub output: Output,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Output {
+    /// Only the script's own output
+    Text,
+    /// The value the script returns as JSON on stdout, null if it returns nothing
+    Json,
+}
+
+impl Output {
+    fn print(&self, status: RunStatus) -> Result<(), anyhow::Error> {
+        debug!("{status}");
+        if let Output::Json = self {
+            let output = status.output.unwrap_or_default();
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        Ok(())
+    }
 }
 
 impl Command for Run {
@@ -34,16 +60,14 @@ impl Command for Run {
                 return Err(anyhow!("No file provided"));
             }
             (None, Some(file)) => {
-                let runner = Language::try_from(file.as_str())?;
+                let runner = Language::from_file(file, config.get_languages())?;
                 let status = runner.run_file(dev, file, args).await?;
-                debug!("{status}");
-                return Ok(());
+                return self.output.print(status);
             }
             (Some(t), Some(file)) => {
-                let runner = Language::try_from(t.as_str())?;
+                let runner = Language::from_file(t, config.get_languages())?;
                 let status = runner.run_file(dev.clone(), file, args).await?;
-                debug!("{status}");
-                return Ok(());
+                return self.output.print(status);
             }
             (None, None) => {}
         }
@@ -55,40 +79,7 @@ impl Command for Run {
             }
         };
 
-        run_alias(config, name, Some(args)).await
-    }
-}
-
-pub async fn run_alias(
-    config: &Config,
-    alias: &str,
-    args: Option<Vec<&str>>,
-) -> Result<(), anyhow::Error> {
-    let args = args.unwrap_or_default();
-
-    let runref = config
-        .get_run(alias)
-        .ok_or(anyhow!("Command '{alias}' not found in config"))?;
-
-    let lang = runref
-        .filetype
-        .as_ref()
-        .ok_or(anyhow!("runner ref filetype not found"))?;
-
-    let dev = Dev::new(config);
-    let file = runref.file.as_ref();
-    let command = runref.command.as_ref();
-    if let Some(f) = file {
-        let dev = Dev::new(config);
-        let status = lang.run_file(dev, f, vec![]).await?;
-        debug!("status: {}", status);
-    }
-
-    if let Some(c) = command {
-        let tmpfilepath = format!("{}{}", config.get_tmp_dir(), lang.get_extension());
-        write_tmp_file(tmpfilepath.as_str(), c, true)?;
-        let status = lang.run_file(dev, tmpfilepath.as_str(), args).await?;
-        debug!("status: {}", status);
+        let status = run_alias(config, name, Some(args)).await?;
+        self.output.print(status)
     }
-    Ok(())
 }
@@ -1,28 +1,51 @@
 use log::{debug, warn};
 use std::{
-    collections::{hash_map::Values, HashMap},
-    env, error, fmt, fs,
+    collections::{hash_map::Values, BTreeMap, HashMap},
+    env, error,
+    ffi::OsStr,
+    fmt, fs,
     fs::File,
     io,
     io::prelude::*,
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
 };
 
 use dirs;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{git::GitRepository, runners::Language};
+use crate::{
+    git::{checkout_remote, GitRepository},
+    runners::{Language, LanguageCommand, LuaOptions, PythonOptions, Sandbox},
+};
 
 const GLOBAL_CONFIG_PATH: &str = "/etc/dev/dev.toml";
+const CONFIG_FILES: [&str; 2] = ["dev.toml", "dev.yaml"];
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
+    /// Other files to take `[run.*]` aliases from, see [`Include`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
     repos: HashMap<String, GitRepository>,
+    #[serde(default)]
     run: HashMap<String, RunRef>,
     #[serde(alias = "env")]
     environment: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    languages: BTreeMap<String, LanguageCommand>,
     #[serde(skip)]
     tmp_dir: String,
+    /// The file this was read from, the project config once loaded
+    #[serde(skip)]
+    file: Option<PathBuf>,
+    /// The project config on its own, what changes are written back to
+    #[serde(skip)]
+    project: Option<Box<Config>>,
+    /// The user's config on its own, what `save_global` writes
+    #[serde(skip)]
+    user: Option<Box<Config>>,
+    #[serde(skip)]
+    origins: BTreeMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -30,11 +53,16 @@ pub struct RunRef {
     pub file: Option<String>,
     pub command: Option<String>,
     pub filetype: Option<Language>,
+    /// Interpreter for `shell` aliases, any program on PATH
+    pub shell: Option<String>,
     pub path: Option<PathBuf>,
     #[serde(alias = "deps")]
     pub dependencies: Option<Vec<String>>,
     #[serde(alias = "env")]
     pub environment: Option<HashMap<String, String>>,
+    pub sandbox: Option<Sandbox>,
+    pub python: Option<PythonOptions>,
+    pub lua: Option<LuaOptions>,
 }
 
 impl Config {
@@ -49,59 +77,327 @@ impl Config {
         }
 
         Config {
+            include: Vec::new(),
             repos,
             run,
             environment: None,
+            languages: BTreeMap::new(),
 
             tmp_dir: "/tmp/dev".to_string(),
+            file: None,
+            project: None,
+            user: None,
+            origins: BTreeMap::new(),
         }
     }
 
-    pub fn load(filepath: PathBuf) -> Result<Config, Error> {
-        let global_config_path = PathBuf::new().join(GLOBAL_CONFIG_PATH);
-        let mut global_config = match read_file(&global_config_path) {
-            Ok(content) => {
-                let mut config = Config::try_from(content)?;
-                config.set_tmp_dir("/tmp/dev");
-                Ok(config)
+    /// The nearest dev.toml or dev.yaml in `dir` or one of its parents,
+    /// stopping at the root of the git work tree `dir` is in.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        for dir in dir.ancestors() {
+            let found = CONFIG_FILES
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|file| file.is_file());
+            if found.is_some() {
+                return found;
             }
-            Err(err) => match err.kind() {
-                io::ErrorKind::NotFound => {
-                    debug!("No config found in this directory, using default settings");
-                    Ok(Config::new(None))
-                }
-                _ => Err(Error::Io(err)),
-            },
-        }?;
-
-        let local_config = match read_file(&filepath) {
-            Ok(content) => {
-                let mut config = Config::try_from(content)?;
-                config.set_tmp_dir("/tmp/dev");
-                Ok(config)
+
+            if dir.join(".git").exists() {
+                break;
             }
-            Err(err) => match err.kind() {
-                io::ErrorKind::NotFound => {
-                    debug!("No config found in this directory, using default settings");
-                    Ok(Config::new(None))
+        }
+
+        None
+    }
+
+    /// Read the config in layers, each overriding the ones before it: the
+    /// system config in /etc/dev, the user's config directory, the project's
+    /// found from the current directory, the file given with `--config` and
+    /// last `DEV_*` environment variables, see [`Config::apply_env`]. Git
+    /// includes come from their cached checkout unless `refresh` is set.
+    pub fn load(filepath: Option<PathBuf>, refresh: bool) -> Result<Config, Error> {
+        let directory = env::current_dir()?;
+        let project_file = Config::find(&directory).unwrap_or(directory.join("dev.toml"));
+
+        let user_file = user_file();
+
+        let mut files = vec![PathBuf::from(GLOBAL_CONFIG_PATH)];
+        files.extend(user_file.clone());
+        files.push(project_file.clone());
+
+        let mut config = Config::new(None);
+        let mut project = Config::new(None);
+        let mut user = None;
+        for file in files.iter().chain(&filepath) {
+            let layers = match Config::read(file, refresh) {
+                Ok(layers) => layers,
+                Err(Error::Io(err))
+                    if err.kind() == io::ErrorKind::NotFound && Some(file) != filepath.as_ref() =>
+                {
+                    debug!("No config found at {}", file.display());
+                    continue;
                 }
-                _ => Err(Error::Io(err)),
-            },
-        }?;
+                Err(e) => return Err(e),
+            };
+
+            if file == &project_file {
+                project = layers.last().cloned().unwrap_or_default();
+            } else if Some(file) == user_file.as_ref() {
+                user = layers.last().cloned().map(Box::new);
+            }
+            for layer in layers {
+                config.merge(layer)?;
+            }
+        }
+
+        project.file = Some(project_file.clone());
+        config.file = Some(project_file);
+        config.project = Some(Box::new(project));
+        config.user = user;
+        config.apply_env(env::vars())?;
+        config.resolve_languages()?;
+
+        Ok(config)
+    }
+
+    /// Read the config at `filepath` and everything it includes, with
+    /// relative paths taken from the file that has them. Includes come first,
+    /// later ones before earlier ones, so merging in order lets the including
+    /// file win over its includes and earlier includes over later ones.
+    fn read(filepath: &Path, refresh: bool) -> Result<Vec<Config>, Error> {
+        let filepath = filepath
+            .canonicalize()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", filepath.display())))?;
+        let mut config: Config = parse(&read_file(&filepath)?, is_yaml(&filepath))?;
+        config.set_tmp_dir("/tmp/dev");
+
+        let base = filepath.parent().unwrap_or(Path::new("/")).to_owned();
+        config.map_paths(|path| resolve(&base, path));
+
+        let mut layers = Vec::new();
+        let mut loading = vec![filepath.clone()];
+        for source in config.include.iter().rev() {
+            layers.extend(Config::include(source, &mut loading, refresh)?);
+        }
 
-        global_config.merge(local_config)
+        config.file = Some(filepath);
+        layers.push(config);
+        Ok(layers)
     }
 
-    fn merge(&mut self, other: Config) -> Result<Config, Error> {
+    /// The aliases and languages from `source` and whatever it includes in
+    /// turn, ordered like [`Config::read`]. `loading` is the chain of files
+    /// being read, starting with the config itself.
+    fn include(
+        source: &str,
+        loading: &mut Vec<PathBuf>,
+        refresh: bool,
+    ) -> Result<Vec<Config>, Error> {
+        #[derive(Deserialize)]
+        struct Included {
+            #[serde(default)]
+            include: Vec<String>,
+            #[serde(default)]
+            run: HashMap<String, RunRef>,
+            #[serde(default)]
+            languages: BTreeMap<String, LanguageCommand>,
+        }
+
+        let base = loading.last().and_then(|file| file.parent());
+        let path = Include::parse(source)?
+            .fetch(base.unwrap_or(Path::new(".")), refresh)?
+            .canonicalize()
+            .map_err(|e| Error::Include(format!("{source}: {e}")))?;
+        if loading.contains(&path) {
+            return Err(Error::Include(format!(
+                "{} includes itself",
+                path.display()
+            )));
+        }
+        debug!("including {}", path.display());
+
+        let included: Included = parse(&read_file(&path)?, is_yaml(&path))?;
+        let mut config = Config::new(None);
+        config.run = included.run;
+        config.languages = included.languages;
+
+        let base = path.parent().unwrap_or(Path::new("/")).to_owned();
+        config.map_paths(|path| resolve(&base, path));
+
+        let mut layers = Vec::new();
+        loading.push(path.clone());
+        for source in included.include.iter().rev() {
+            layers.extend(Config::include(source, loading, refresh)?);
+        }
+        loading.pop();
+
+        config.file = Some(path);
+        layers.push(config);
+        Ok(layers)
+    }
+
+    /// Lay `other` over this config, keeping track of where its values came
+    /// from.
+    fn merge(&mut self, other: Config) -> Result<(), Error> {
+        if let Some(file) = &other.file {
+            let origin = file.display().to_string();
+            for key in other.values()?.into_keys() {
+                self.origins.insert(key, origin.clone());
+            }
+        }
+
+        self.include.extend(other.include);
         self.repos.extend(other.repos);
         self.run.extend(other.run);
-        match (self.environment.clone(), other.environment) {
-            (Some(mut this), Some(other)) => this.extend(other),
-            (Some(_), None) => {},
-            (None, Some(other)) => self.environment = Some(other),
-            (None, None) => {},
+        self.languages.extend(other.languages);
+        if let Some(environment) = other.environment {
+            self.environment
+                .get_or_insert_with(HashMap::new)
+                .extend(environment);
+        }
+        Ok(())
+    }
+
+    /// Override single values with `DEV_<SECTION>__<KEY>` variables, like
+    /// `DEV_ENVIRONMENT__TOKEN=abc` or `DEV_RUN__build__command="make"`. The
+    /// section is case insensitive, keys aren't. Values are read as TOML when
+    /// they parse and as a string otherwise. Variables without a key, which
+    /// would replace a whole section, are skipped and so are ones that don't
+    /// fit the config, with a warning naming them.
+    fn apply_env(&mut self, vars: impl Iterator<Item = (String, String)>) -> Result<(), Error> {
+        fn set(value: &mut toml::Value, path: &[String], new: toml::Value) -> Result<(), Error> {
+            let (last, parents) = path.split_last().expect("split always has a first part");
+            let mut table = value.as_table_mut().expect("config is a table");
+            for key in parents {
+                table = table
+                    .entry(key)
+                    .or_insert_with(|| toml::Table::new().into())
+                    .as_table_mut()
+                    .ok_or(Error::Merge(format!("{key} is not a table")))?;
+            }
+            table.insert(last.clone(), new);
+            Ok(())
+        }
+
+        let mut value = toml::Value::try_from(&*self)?;
+        let mut overridden = None;
+
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix("DEV_") else {
+                continue;
+            };
+            let mut path: Vec<String> = key.split("__").map(str::to_string).collect();
+            path[0] = match path[0].to_lowercase().as_str() {
+                "env" | "environment" => "environment".to_string(),
+                section @ ("include" | "repos" | "run" | "languages") => section.to_string(),
+                _ => continue,
+            };
+            if path.len() < 2 {
+                debug!("Ignoring {name}, set a single value with {name}__<KEY>");
+                continue;
+            }
+
+            let parsed = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(toml::Value::String(raw));
+
+            let mut next = value.clone();
+            let config = set(&mut next, &path, parsed)
+                .and_then(|()| Ok(next.clone().try_into::<Config>()?));
+            match config {
+                Ok(config) => {
+                    value = next;
+                    overridden = Some(config);
+                    self.origins.insert(path.join("."), name);
+                }
+                Err(e) => warn!("Ignoring {name}: {e}"),
+            }
+        }
+
+        if let Some(config) = overridden {
+            *self = Config {
+                tmp_dir: self.tmp_dir.clone(),
+                file: self.file.take(),
+                project: self.project.take(),
+                user: self.user.take(),
+                origins: std::mem::take(&mut self.origins),
+                ..config
+            };
+        }
+        Ok(())
+    }
+
+    /// Check the `[languages]` against the built in ones and look up every
+    /// alias' filetype that isn't built in, once all the layers are merged so
+    /// an alias can use a language from any of them.
+    fn resolve_languages(&mut self) -> Result<(), Error> {
+        for (name, language) in &self.languages {
+            if Language::is_builtin(name, &language.extensions) {
+                return Err(Error::Language(format!(
+                    "languages.{name}: conflicts with a built in language"
+                )));
+            }
+        }
+
+        for (alias, run) in self.run.iter_mut() {
+            if let Some(filetype) = &mut run.filetype {
+                filetype
+                    .resolve(&self.languages)
+                    .map_err(|e| Error::Language(format!("run.{alias}: {e}")))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every value in the config by its dotted key, arrays are kept whole.
+    pub fn values(&self) -> Result<BTreeMap<String, toml::Value>, Error> {
+        fn flatten(prefix: &str, value: toml::Value, values: &mut BTreeMap<String, toml::Value>) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (key, value) in table {
+                        let key = match prefix {
+                            "" => key,
+                            prefix => format!("{prefix}.{key}"),
+                        };
+                        flatten(&key, value, values);
+                    }
+                }
+                value => {
+                    values.insert(prefix.to_string(), value);
+                }
+            }
+        }
+
+        let mut values = BTreeMap::new();
+        flatten("", toml::Value::try_from(self)?, &mut values);
+        Ok(values)
+    }
+
+    /// The file, or environment variable, the value at `key` was set by.
+    pub fn origin(&self, key: &str) -> Option<&str> {
+        self.origins.get(key).map(String::as_str)
+    }
+
+    /// Rewrite the repo paths and every path in the aliases with `f`.
+    fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        for repo in self.repos.values_mut() {
+            if let Some(path) = &mut repo.path {
+                *path = f(Path::new(path)).to_string_lossy().into_owned();
+            }
         }
-        Ok(self.to_owned())
+        for run in self.run.values_mut() {
+            run.map_paths(&f);
+        }
+    }
+
+    /// Where the project config is, or would be created.
+    fn project_file(&self) -> PathBuf {
+        self.file.clone().unwrap_or_else(|| {
+            let directory = env::current_dir().expect("error getting current directory");
+            directory.join("dev.toml")
+        })
     }
 
     pub fn get_repo(&self, repo: &str) -> Option<&GitRepository> {
@@ -120,26 +416,37 @@ impl Config {
         self.repos.get_mut(repo)
     }
 
+    pub fn get_languages(&self) -> &BTreeMap<String, LanguageCommand> {
+        &self.languages
+    }
+
     pub fn get_env_vars(&self) -> Option<&HashMap<String, String>> {
         self.environment.as_ref()
     }
 
     pub fn update_repo(&mut self, repo: GitRepository) -> Result<(), Error> {
-        self.repos.insert(repo.name.clone(), repo);
-        let directory = env::current_dir().expect("error getting current directory");
-
-        write_file(&PathBuf::new().join(directory).join("dev.toml"), self)?;
-
-        Ok(())
+        let repo = from_current_dir(repo)?;
+        self.update_project(|config| {
+            config.repos.insert(repo.name.clone(), repo.clone());
+        })
     }
     pub fn update(&self) -> Result<(), Error> {
-        let directory = env::current_dir().expect("error getting current directory");
-
-        write_file(&PathBuf::new().join(directory).join("dev.toml"), self)?;
+        let project = self.project.as_deref().unwrap_or(self);
+        write_file(&self.project_file(), project)?;
 
         Ok(())
     }
 
+    /// Make `change` to the loaded config and the project config on its own,
+    /// then write the project config back out.
+    fn update_project(&mut self, change: impl Fn(&mut Config)) -> Result<(), Error> {
+        change(self);
+        if let Some(project) = self.project.as_deref_mut() {
+            change(project);
+        }
+        self.update()
+    }
+
     pub fn add_repo(
         &mut self,
         name: Option<String>,
@@ -156,26 +463,29 @@ impl Config {
             None => git_repo.name.clone(),
         };
 
-        self.repos.insert(name, git_repo.to_owned());
-
-        let directory = env::current_dir().expect("error getting current directory");
-
-        write_file(&PathBuf::new().join(directory).join("dev.toml"), self)?;
+        let git_repo = from_current_dir(git_repo.to_owned())?;
+        self.update_project(|config| {
+            config.repos.insert(name.clone(), git_repo.clone());
+        })?;
 
         Ok(self)
     }
 
+    /// Write the user's config back out, or create it when there's none.
+    /// Only what was read from it is written, never the other layers.
     pub fn save_global(&self) -> Result<(), Error> {
-        let config_dir = dirs::config_dir().unwrap().join("dev");
-        if !config_dir.is_dir() {
-            fs::create_dir(&config_dir)?;
+        let config_file = user_file().ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no config directory",
+        ))?;
+        if let Some(config_dir) = config_file.parent() {
+            fs::create_dir_all(config_dir)?;
         }
 
-        let config_file = config_dir.join("dev.toml");
         if !config_file.is_file() {
             create_new(&config_file)?;
-        } else {
-            write_file(&config_file, self)?;
+        } else if let Some(user) = &self.user {
+            write_file(&config_file, user)?;
         }
 
         Ok(())
@@ -194,13 +504,186 @@ impl Config {
     }
 }
 
+impl RunRef {
+    fn map_paths(&mut self, f: &impl Fn(&Path) -> PathBuf) {
+        if let Some(file) = &mut self.file {
+            *file = f(Path::new(file)).to_string_lossy().into_owned();
+        }
+        if let Some(path) = &mut self.path {
+            *path = f(path);
+        }
+        if let Some(venv) = self.python.as_mut().and_then(|python| python.venv.as_mut()) {
+            *venv = f(venv);
+        }
+        if let Some(lib) = self.lua.as_mut().and_then(|lua| lua.lib.as_mut()) {
+            *lib = f(lib);
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new(None)
+    }
+}
+
 impl TryFrom<String> for Config {
     type Error = Error;
     fn try_from(s: String) -> Result<Self, Error> {
-        match toml::from_str::<Config>(&s) {
-            Ok(config) => Ok(config),
-            Err(e) => Err(Error::TomlDe(e)),
+        let mut config: Config = parse(&s, false)?;
+        config.resolve_languages()?;
+        Ok(config)
+    }
+}
+
+/// Where an `include` entry is read from, either a path relative to the
+/// file including it or a file in another repository:
+///
+/// ```toml
+/// include = [
+///     "tasks/shared.toml",
+///     "git+ssh://git@github.com/org/tasks.git#tasks.toml",
+///     "git+https://github.com/org/tasks.git@v1.2.0#rust/tasks.toml",
+/// ]
+/// ```
+///
+/// Repositories are checked out at the `@ref` given, or their default
+/// branch, under the user's cache directory.
+#[derive(Debug, PartialEq)]
+enum Include {
+    File(PathBuf),
+    Git {
+        url: String,
+        reference: Option<String>,
+        file: String,
+    },
+}
+
+impl Include {
+    fn parse(source: &str) -> Result<Self, Error> {
+        let Some(remote) = source.strip_prefix("git+") else {
+            return Ok(Include::File(PathBuf::from(source)));
+        };
+
+        let (repo, file) = remote.split_once('#').ok_or(Error::Include(format!(
+            "{source} is missing the #file to include"
+        )))?;
+
+        // scp style urls and ssh users have an @ too, a ref never has a
+        // / or : after it
+        let (url, reference) = match repo.rsplit_once('@') {
+            Some((url, reference)) if !reference.contains(['/', ':']) => {
+                (url, Some(reference.to_string()))
+            }
+            _ => (repo, None),
+        };
+
+        Ok(Include::Git {
+            url: url.to_string(),
+            reference,
+            file: file.to_string(),
+        })
+    }
+
+    /// The local path of the file, checking out its repository first for
+    /// `git+` includes, see [`checkout_remote`].
+    fn fetch(&self, base: &Path, refresh: bool) -> Result<PathBuf, Error> {
+        match self {
+            Include::File(path) => Ok(base.join(path)),
+            Include::Git {
+                url,
+                reference,
+                file,
+            } => {
+                let name: String = format!("{url}@{}", reference.as_deref().unwrap_or("HEAD"))
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                let checkout = dirs::cache_dir()
+                    .unwrap_or_else(env::temp_dir)
+                    .join("dev/includes")
+                    .join(name);
+                if let Some(parent) = checkout.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                checkout_remote(url, reference.as_deref(), &checkout, refresh)
+                    .map_err(|e| Error::Include(format!("{url}: {e}")))?;
+
+                Ok(checkout.join(file))
+            }
+        }
+    }
+}
+
+/// The config in the user's config directory.
+fn user_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("dev/dev.toml"))
+}
+
+fn parse<T: DeserializeOwned>(s: &str, yaml: bool) -> Result<T, Error> {
+    if yaml {
+        Ok(serde_yaml::from_str::<T>(s)?)
+    } else {
+        Ok(toml::from_str::<T>(s)?)
+    }
+}
+
+fn is_yaml(filepath: &Path) -> bool {
+    matches!(
+        filepath.extension().and_then(OsStr::to_str),
+        Some("yaml" | "yml")
+    )
+}
+
+/// `path` taken relative to `base`, without any `.` in between.
+fn resolve(base: &Path, path: &Path) -> PathBuf {
+    base.join(path).components().collect()
+}
+
+/// `repo` with its path, given on the command line, taken from the current
+/// directory.
+fn from_current_dir(mut repo: GitRepository) -> Result<GitRepository, Error> {
+    if let Some(path) = &mut repo.path {
+        let directory = env::current_dir()?;
+        *path = resolve(&directory, Path::new(path))
+            .to_string_lossy()
+            .into_owned();
+    }
+    Ok(repo)
+}
+
+/// The inverse of [`resolve`], for writing paths back out. Paths outside
+/// `base` get a `..` for every directory left in it, only paths that share
+/// nothing with it, e.g. on another drive, stay absolute.
+fn relative(base: &Path, path: &Path) -> PathBuf {
+    if !path.is_absolute() {
+        return path.to_owned();
+    }
+
+    let mut base = base.components().peekable();
+    let mut rest = path.components().peekable();
+    let mut shared = false;
+    while let (Some(a), Some(b)) = (base.peek(), rest.peek()) {
+        if a != b {
+            break;
         }
+        base.next();
+        rest.next();
+        shared = true;
+    }
+    if !shared {
+        return path.to_owned();
+    }
+
+    let relative = base
+        .map(|_| Component::ParentDir)
+        .chain(rest)
+        .collect::<PathBuf>();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
     }
 }
 
@@ -232,8 +715,17 @@ fn read_file(filepath: &PathBuf) -> Result<String, io::Error> {
 fn write_file(filepath: &PathBuf, config: &Config) -> Result<Config, Error> {
     let file = File::options().write(true).truncate(true).open(filepath);
 
-    let toml_str = toml::to_string(&config)?;
-    file?.write_all(toml_str.as_bytes())?;
+    let mut written = config.clone();
+    if let Some(base) = filepath.canonicalize()?.parent() {
+        written.map_paths(|path| relative(base, path));
+    }
+
+    let content = if is_yaml(filepath) {
+        serde_yaml::to_string(&written)?
+    } else {
+        toml::to_string(&written)?
+    };
+    file?.write_all(content.as_bytes())?;
 
     Ok(config.to_owned())
 }
@@ -243,8 +735,11 @@ pub enum Error {
     Io(io::Error),
     TomlDe(toml::de::Error),
     TomlSer(toml::ser::Error),
+    Yaml(serde_yaml::Error),
     Duplicate(String),
     Merge(String),
+    Include(String),
+    Language(String),
     NotFound,
 }
 
@@ -254,8 +749,11 @@ impl fmt::Display for Error {
             Error::Io(e) => e.fmt(f),
             Error::TomlDe(e) => e.fmt(f),
             Error::TomlSer(e) => e.fmt(f),
+            Error::Yaml(e) => e.fmt(f),
             Error::Duplicate(e) => e.fmt(f),
             Error::Merge(e) => e.fmt(f),
+            Error::Include(e) => e.fmt(f),
+            Error::Language(e) => e.fmt(f),
             Error::NotFound => self.fmt(f),
         }
     }
@@ -280,3 +778,244 @@ impl From<toml::ser::Error> for Error {
         Error::TomlSer(e)
     }
 }
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Error::Yaml(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_includes() {
+        assert_eq!(
+            Include::parse("tasks/shared.toml").unwrap(),
+            Include::File(PathBuf::from("tasks/shared.toml"))
+        );
+        assert_eq!(
+            Include::parse("git+ssh://git@github.com/org/tasks.git#tasks.toml").unwrap(),
+            Include::Git {
+                url: "ssh://git@github.com/org/tasks.git".to_string(),
+                reference: None,
+                file: "tasks.toml".to_string(),
+            }
+        );
+        assert_eq!(
+            Include::parse("git+git@github.com:org/tasks.git@v1.2.0#rust/tasks.toml").unwrap(),
+            Include::Git {
+                url: "git@github.com:org/tasks.git".to_string(),
+                reference: Some("v1.2.0".to_string()),
+                file: "rust/tasks.toml".to_string(),
+            }
+        );
+        assert!(Include::parse("git+https://github.com/org/tasks.git").is_err());
+    }
+
+    #[test]
+    fn include_aliases() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(
+            dir.join("dev.toml"),
+            r#"
+            include = ["shared/tasks.toml"]
+            [repos]
+            [run.build]
+            command = "make"
+            filetype = "sh"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("shared/tasks.toml"),
+            r#"
+            include = ["lint.toml"]
+            [run.build]
+            command = "cargo build"
+            filetype = "sh"
+            [run.test]
+            file = "scripts/test.sh"
+            filetype = "sh"
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("shared/lint.toml"),
+            r#"
+            include = ["tasks.toml"]
+            [run.lint]
+            command = "cargo clippy"
+            filetype = "sh"
+            "#,
+        )
+        .unwrap();
+
+        let err = Config::read(&dir.join("dev.toml"), false).unwrap_err();
+        assert!(err.to_string().ends_with("tasks.toml includes itself"));
+
+        fs::write(
+            dir.join("shared/lint.toml"),
+            "[run.lint]\ncommand = \"cargo clippy\"\nfiletype = \"sh\"\n",
+        )
+        .unwrap();
+        let mut config = Config::new(None);
+        for layer in Config::read(&dir.join("dev.toml"), false).unwrap() {
+            config.merge(layer).unwrap();
+        }
+        let command = |alias| config.get_run(alias).unwrap().command.as_deref();
+        assert_eq!(command("build"), Some("make"));
+        assert_eq!(command("lint"), Some("cargo clippy"));
+
+        let file = config.get_run("test").unwrap().file.as_deref();
+        let expected = dir.canonicalize().unwrap().join("shared/scripts/test.sh");
+        assert_eq!(file.map(PathBuf::from), Some(expected));
+
+        let origin = dir.canonicalize().unwrap().join("shared/lint.toml");
+        assert_eq!(config.origin("run.lint.command"), origin.to_str());
+    }
+
+    #[test]
+    fn layers_and_overrides() {
+        let mut config = Config::new(None);
+        let layers = [
+            (
+                "/etc/dev/dev.toml",
+                r#"
+                [repos]
+                [environment]
+                REGISTRY = "registry.internal"
+                LEVEL = "info"
+                [run.build]
+                command = "make"
+                filetype = "sh"
+                "#,
+            ),
+            (
+                "/project/dev.toml",
+                r#"
+                [repos]
+                [environment]
+                LEVEL = "debug"
+                "#,
+            ),
+        ];
+        for (file, content) in layers {
+            let mut layer = Config::try_from(content.to_string()).unwrap();
+            layer.file = Some(PathBuf::from(file));
+            config.merge(layer).unwrap();
+        }
+
+        let vars = [
+            ("DEV_RUN__build__dependencies", r#"["lint"]"#),
+            ("DEV_ENV__TOKEN", "abc"),
+            ("DEVELOPER", "me"),
+            ("DEV_UNKNOWN", "x"),
+            ("DEV_ENV", "production"),
+            ("DEV_REPOS__alpha", "1"),
+            ("DEV_ENV__LEVEL__NESTED", "x"),
+        ];
+        config
+            .apply_env(
+                vars.map(|(k, v)| (k.to_string(), v.to_string()))
+                    .into_iter(),
+            )
+            .unwrap();
+
+        let env = config.get_env_vars().unwrap();
+        assert_eq!(env["REGISTRY"], "registry.internal");
+        assert_eq!(env["LEVEL"], "debug");
+        assert_eq!(env["TOKEN"], "abc");
+        assert!(config.get_repo("alpha").is_none());
+        let build = config.get_run("build").unwrap();
+        assert_eq!(build.command.as_deref(), Some("make"));
+        assert_eq!(build.dependencies, Some(vec!["lint".to_string()]));
+
+        assert_eq!(
+            config.origin("environment.REGISTRY"),
+            Some("/etc/dev/dev.toml")
+        );
+        assert_eq!(
+            config.origin("environment.LEVEL"),
+            Some("/project/dev.toml")
+        );
+        assert_eq!(config.origin("environment.TOKEN"), Some("DEV_ENV__TOKEN"));
+        assert_eq!(
+            config.origin("run.build.dependencies"),
+            Some("DEV_RUN__build__dependencies")
+        );
+    }
+
+    #[test]
+    fn resolve_languages() {
+        let mut config = Config::new(None);
+        let user = "[repos]\n[languages.ruby]\ncommand = \"ruby\"\nextensions = [\"rb\"]\n";
+        let project = "[repos]\n[run.task]\nfile = \"task.rb\"\nfiletype = \"ruby\"\n";
+        for layer in [user, project] {
+            config.merge(parse(layer, false).unwrap()).unwrap();
+        }
+        config.resolve_languages().unwrap();
+
+        let task = config.get_run("task").unwrap();
+        let filetype = task.filetype.as_ref().unwrap();
+        assert_eq!(filetype.get_name(), "ruby");
+        assert_eq!(filetype.get_extension(), ".rb");
+
+        // Languages don't outlive the config that defined them
+        let error = Config::try_from(project.to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "run.task: Unsupported language: ruby");
+
+        let shadowing = "[repos]\n[languages.node]\ncommand = \"node\"\nextensions = [\"js\"]\n";
+        let error = Config::try_from(shadowing.to_string()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "languages.node: conflicts with a built in language"
+        );
+    }
+
+    #[test]
+    fn find_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("project/.git")).unwrap();
+        fs::create_dir_all(dir.join("project/src/bin")).unwrap();
+        fs::write(dir.join("dev.toml"), "").unwrap();
+
+        assert_eq!(Config::find(&dir.join("project/src/bin")), None);
+
+        fs::write(dir.join("project/dev.yaml"), "").unwrap();
+        assert_eq!(
+            Config::find(&dir.join("project/src/bin")),
+            Some(dir.join("project/dev.yaml"))
+        );
+
+        // Paths are written back relative to the file, even outside of it
+        let dir = dir.canonicalize().unwrap();
+        let file = dir.join("project/dev.toml");
+        let mut config = Config::new(None);
+        for (name, path) in [
+            ("alpha", "project/src"),
+            ("beta", "shared/beta"),
+            ("root", ""),
+        ] {
+            let repo = GitRepository {
+                org: None,
+                name: name.to_string(),
+                url: None,
+                path: Some(dir.join(path).to_string_lossy().into_owned()),
+            };
+            config.repos.insert(name.to_string(), repo);
+        }
+        fs::write(&file, "").unwrap();
+        write_file(&file, &config).unwrap();
+
+        let written: Config = parse(&fs::read_to_string(&file).unwrap(), false).unwrap();
+        let path = |name: &str| written.get_repo(name).unwrap().path.clone().unwrap();
+        assert_eq!(path("alpha"), "src");
+        assert_eq!(path("beta"), "../shared/beta");
+        assert_eq!(path("root"), "..");
+    }
+}
@@ -1,3 +1,3 @@
 pub mod repo;
-pub use repo::{GitError, GitRepository};
+pub use repo::{checkout_remote, GitError, GitRepository};
 pub mod scan;
@@ -440,8 +440,70 @@ fn merge<'a>(
     Ok(())
 }
 
+/// Check out `reference` (the remote's default branch when there's none) of
+/// the repository at `url` into `path`, cloning it the first time. Later calls
+/// use what's already in `path` and only fetch when `reference` isn't there or
+/// `refresh` is set, keeping the last checkout when the remote can't be
+/// reached.
+pub fn checkout_remote(
+    url: &str,
+    reference: Option<&str>,
+    path: &Path,
+    refresh: bool,
+) -> Result<Repository, GitError> {
+    let (repo, cloned) = match Repository::open(path) {
+        Ok(repo) => (repo, false),
+        Err(_) => {
+            let mut fo = git2::FetchOptions::new();
+            fo.remote_callbacks(credentials());
+            let repo = git2::build::RepoBuilder::new()
+                .fetch_options(fo)
+                .clone(url, path)?;
+            (repo, true)
+        }
+    };
+
+    let candidates = match reference {
+        Some(reference) => [format!("refs/remotes/origin/{reference}"), reference.into()],
+        None => ["refs/remotes/origin/HEAD".into(), "HEAD".into()],
+    };
+
+    let missing = candidates
+        .iter()
+        .all(|candidate| repo.revparse_single(candidate).is_err());
+    if !cloned && (refresh || missing) {
+        let fetched = repo.find_remote("origin").and_then(|mut remote| {
+            let mut fo = git2::FetchOptions::new();
+            fo.remote_callbacks(credentials());
+            fo.download_tags(git2::AutotagOption::All);
+            remote.fetch::<&str>(&[], Some(&mut fo), None)
+        });
+        if let Err(e) = fetched {
+            warn!("Unable to fetch {url}, using the cached checkout: {e}");
+        }
+    }
+
+    {
+        let commit = candidates
+            .iter()
+            .find_map(|candidate| repo.revparse_single(candidate).ok())
+            .ok_or(git2::Error::from_str(&format!(
+                "{} not found in {url}",
+                reference.unwrap_or("HEAD")
+            )))?
+            .peel_to_commit()?;
+
+        let mut cb = git2::build::CheckoutBuilder::new();
+        cb.force();
+        repo.checkout_tree(commit.as_object(), Some(&mut cb))?;
+        repo.set_head_detached(commit.id())?;
+    }
+
+    Ok(repo)
+}
+
 fn callbacks() -> RemoteCallbacks<'static> {
-    let mut callbacks = RemoteCallbacks::new();
+    let mut callbacks = credentials();
     callbacks.transfer_progress(|stats| {
         if stats.received_objects() == stats.total_objects() {
             print!(
@@ -462,6 +524,11 @@ fn callbacks() -> RemoteCallbacks<'static> {
         true
     });
 
+    callbacks
+}
+
+fn credentials() -> RemoteCallbacks<'static> {
+    let mut callbacks = RemoteCallbacks::new();
     callbacks.credentials(|_url, username_from_url, _allowed_types| {
         Cred::ssh_key(
             username_from_url.unwrap(),
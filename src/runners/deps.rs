@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+
+/// `alias` and everything it depends on, each dependency ahead of the aliases
+/// that need it and only once however many of them do.
+pub(super) fn run_order(config: &Config, alias: &str) -> Result<Vec<String>> {
+    fn visit(
+        config: &Config,
+        alias: &str,
+        stack: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|done| done == alias) {
+            return Ok(());
+        }
+        if stack.iter().any(|parent| parent == alias) {
+            return Err(anyhow!(
+                "Dependency cycle: {} -> {alias}",
+                stack.join(" -> ")
+            ));
+        }
+
+        let runref = config
+            .get_run(alias)
+            .ok_or(anyhow!("Command '{alias}' not found in config"))?;
+
+        stack.push(alias.to_string());
+        for dependency in runref.dependencies.iter().flatten() {
+            visit(config, dependency, stack, order)?;
+        }
+        stack.pop();
+
+        order.push(alias.to_string());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(config, alias, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+/// Fill in the `{{deps.<alias>.output.<key>}}` placeholders in `template`
+/// from `deps`. Strings go in as they are, anything else as JSON. Braces
+/// that aren't a `deps` placeholder are left alone, they're common enough in
+/// scripts.
+pub(super) fn render(template: &str, deps: &Map<String, Value>) -> Result<String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let (before, after) = rest.split_at(start);
+        rendered.push_str(before);
+
+        let placeholder = after
+            .find("}}")
+            .map(|end| (after[2..end].trim(), end))
+            .filter(|(path, _)| path.starts_with("deps."));
+        let Some((path, end)) = placeholder else {
+            rendered.push_str("{{");
+            rest = &after[2..];
+            continue;
+        };
+
+        let value = path["deps.".len()..]
+            .split_once('.')
+            .and_then(|(alias, path)| lookup(deps.get(alias)?, path))
+            .ok_or(anyhow!("Unknown value {{{{{path}}}}}"))?;
+
+        match value {
+            Value::String(s) => rendered.push_str(s),
+            value => rendered.push_str(&value.to_string()),
+        }
+        rest = &after[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Walk a dotted path into `value`, numbers index into arrays.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn render_dependency_outputs() {
+        let deps = json!({
+            "version": { "exit_code": 0, "output": { "tag": "v1.2.0", "files": ["a", "b"] } },
+        });
+        let deps = deps.as_object().unwrap();
+
+        assert_eq!(
+            render(
+                "git tag {{ deps.version.output.tag }} {{deps.version.output.files.1}}",
+                deps
+            )
+            .unwrap(),
+            "git tag v1.2.0 b"
+        );
+        assert_eq!(
+            render("{{deps.version.output.files}}", deps).unwrap(),
+            r#"["a","b"]"#
+        );
+        assert_eq!(render("t = {{1, 2}}", deps).unwrap(), "t = {{1, 2}}");
+        assert!(render("{{deps.missing.output}}", deps).is_err());
+    }
+
+    #[test]
+    fn dependency_order() {
+        let config = Config::try_from(
+            r#"
+            [repos]
+
+            [run.release]
+            command = "true"
+            filetype = "sh"
+            deps = ["tag", "version"]
+
+            [run.tag]
+            command = "true"
+            filetype = "sh"
+            deps = ["version"]
+
+            [run.version]
+            command = "true"
+            filetype = "sh"
+
+            [run.loop]
+            command = "true"
+            filetype = "sh"
+            deps = ["loop"]
+            "#
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            run_order(&config, "release").unwrap(),
+            vec!["version", "tag", "release"]
+        );
+        assert!(run_order(&config, "loop").is_err());
+    }
+}
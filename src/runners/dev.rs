@@ -1,33 +1,42 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use serde::{Deserialize, Serialize};
-
-#[cfg(feature = "python")]
-use pyo3::prelude::*;
+use serde_json::{Map, Value};
 
 use crate::config::Config;
 
 #[derive(Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "python", derive(FromPyObject))]
-#[cfg_attr(feature = "python", pyo3(from_item_all))]
 pub struct Dev {
     pub version: String,
     pub dir: PathBuf,
     pub steps: Vec<String>,
+    /// What the aliases this one depends on handed back, keyed by alias
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub deps: Map<String, Value>,
 
     environment: HashMap<String, String>,
+    // Scripts only ever hand back the fields above, the config is for the
+    // host functions that look up repos and aliases
+    #[serde(skip)]
+    config: Arc<Config>,
 }
 
 impl Dev {
     pub fn new(config: &Config) -> Self {
         Self {
-            version: "0.1.0".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
             environment: config.get_env_vars().unwrap_or(&HashMap::default()).clone(),
             dir: PathBuf::new(),
             steps: Vec::new(),
+            deps: Map::new(),
+            config: Arc::new(config.clone()),
         }
     }
 
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn get_version(&self) -> String {
         self.version.clone()
     }
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use super::{dev::Dev, language, shell::run_process, RunStatus};
+
+/// A language run by an external program, configured as
+///
+/// ```toml
+/// [languages.ruby]
+/// extensions = ["rb"]
+/// command = "ruby {file} {args}"
+/// ```
+///
+/// The template is split into words like a shell would, `{file}` is replaced
+/// with the script and `{args}` with the arguments as separate words, either
+/// is appended when the template leaves it out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LanguageCommand {
+    pub command: String,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+
+impl LanguageCommand {
+    fn expand(&self, file: &str, args: &[&str]) -> Result<Vec<String>, anyhow::Error> {
+        let template = shlex::split(&self.command)
+            .ok_or(anyhow!("Unbalanced quotes in command: {}", self.command))?;
+
+        let mut words = Vec::new();
+        let (mut has_file, mut has_args) = (false, false);
+
+        for word in template {
+            if word == "{args}" {
+                words.extend(args.iter().map(|arg| arg.to_string()));
+                has_args = true;
+            } else {
+                has_file |= word.contains("{file}");
+                words.push(word.replace("{file}", file));
+            }
+        }
+
+        if !has_file {
+            words.push(file.to_string());
+        }
+        if !has_args {
+            words.extend(args.iter().map(|arg| arg.to_string()));
+        }
+
+        Ok(words)
+    }
+}
+
+/// A user defined language, a filetype that isn't built in is only a name
+/// until the config it's in is loaded and [`ExternalLanguage::resolve`]
+/// finds it in `[languages]`.
+#[derive(Debug, Clone)]
+pub struct ExternalLanguage {
+    name: String,
+    extension: String,
+    language: Option<LanguageCommand>,
+}
+
+impl ExternalLanguage {
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            extension: String::new(),
+            language: None,
+        }
+    }
+
+    /// The language in `languages` handling files ending in `extension`.
+    pub fn from_extension(
+        extension: &str,
+        languages: &BTreeMap<String, LanguageCommand>,
+    ) -> Option<Self> {
+        let (name, _) = languages
+            .iter()
+            .find(|(_, language)| language.extensions.iter().any(|ext| ext == extension))?;
+
+        let mut language = Self::named(name);
+        language.resolve(languages);
+        Some(language)
+    }
+
+    /// Look this language up in `languages`, false when it isn't there.
+    pub fn resolve(&mut self, languages: &BTreeMap<String, LanguageCommand>) -> bool {
+        let Some(language) = languages.get(&self.name) else {
+            return false;
+        };
+
+        self.extension = language
+            .extensions
+            .first()
+            .map(|ext| format!(".{ext}"))
+            .unwrap_or_default();
+        self.language = Some(language.clone());
+        true
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_extension(&self) -> &str {
+        &self.extension
+    }
+}
+
+#[async_trait]
+impl language::LanguageFunctions for ExternalLanguage {
+    async fn run_file(
+        &self,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        let language = self
+            .language
+            .as_ref()
+            .ok_or(anyhow!("Unsupported language: {}", self.name))?;
+        let words = language.expand(file, &args)?;
+        debug!("running {}: {}", self.name, words.join(" "));
+
+        let mut command = Command::new(&words[0]);
+        command.args(&words[1..]).envs(dev.get_env());
+
+        run_process(command, file).await
+    }
+
+    async fn load_file(&self, _file: &str) -> Result<(), anyhow::Error> {
+        Err(anyhow!("loading files is not supported for {}", self.name))
+    }
+
+    async fn run_shell(
+        &self,
+        _command: &str,
+        _args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        Err(anyhow!("{} has no interactive shell", self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_template() {
+        let language = LanguageCommand {
+            command: "deno run -A {file} {args}".to_string(),
+            extensions: vec![],
+        };
+        assert_eq!(
+            language.expand("task.ts", &["a b", "c"]).unwrap(),
+            vec!["deno", "run", "-A", "task.ts", "a b", "c"]
+        );
+
+        let language = LanguageCommand {
+            command: "ruby".to_string(),
+            extensions: vec![],
+        };
+        assert_eq!(
+            language.expand("task.rb", &["x"]).unwrap(),
+            vec!["ruby", "task.rb", "x"]
+        );
+
+        let language = LanguageCommand {
+            command: r#"python3 -c "import sys; print(sys.argv)" '{file}'"#.to_string(),
+            extensions: vec![],
+        };
+        assert_eq!(
+            language.expand("my task.py", &[]).unwrap(),
+            vec!["python3", "-c", "import sys; print(sys.argv)", "my task.py"]
+        );
+
+        let language = LanguageCommand {
+            command: r#"ruby -e "puts 1"#.to_string(),
+            extensions: vec![],
+        };
+        assert!(language.expand("task.rb", &[]).is_err());
+    }
+}
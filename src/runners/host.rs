@@ -0,0 +1,390 @@
+use std::{env, fs, future::Future, path::PathBuf, process::Command, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use log::{log, Level};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::{run_alias, Capability, Dev};
+use crate::git::GitRepository;
+
+/// A function of the `dev` host API. Every embedded runner generates its
+/// bindings from [`HOST_API`], so the functions, their arguments and what
+/// they return are the same in each language; only the casing changes,
+/// JavaScript gets `getVersion` where the others get `get_version`.
+pub struct HostFunction {
+    pub name: &'static str,
+    /// Argument names, optional ones end in `?`
+    pub params: &'static [&'static str],
+    /// Blocking functions do I/O, runners call them off the script thread
+    /// with a copy of the build state, so they never change it
+    pub blocking: bool,
+    /// What a sandboxed alias needs to be allowed to call this
+    pub capability: Option<Capability>,
+    function: fn(&mut Dev, &Args) -> Result<Value>,
+}
+
+pub static HOST_API: &[HostFunction] = &[
+    HostFunction::new("get_version", &[], get_version),
+    HostFunction::new("set_version", &["version"], set_version),
+    HostFunction::new("get_dir", &[], get_dir),
+    HostFunction::new("set_dir", &["dir"], set_dir),
+    HostFunction::new("get_work_dir", &[], get_work_dir),
+    HostFunction::new("get_env", &["key?"], get_env),
+    HostFunction::new("set_env", &["key", "value"], set_env),
+    HostFunction::new("get_steps", &[], get_steps),
+    HostFunction::new("add_step", &["step"], add_step),
+    HostFunction::new("get_deps", &[], get_deps),
+    HostFunction::new("get_repos", &[], get_repos),
+    HostFunction::new("get_repo", &["name"], get_repo),
+    HostFunction::new("get_alias", &["name"], get_alias),
+    HostFunction::new("run", &["alias", "args?"], run).blocking(Capability::Process),
+    HostFunction::new("exec", &["command", "args?"], exec).blocking(Capability::Process),
+    HostFunction::new("git_fetch", &["path"], git_fetch).blocking(Capability::Net),
+    HostFunction::new("read_yaml", &["path"], read_yaml).blocking(Capability::FsRead),
+    HostFunction::new("write_yaml", &["path", "value"], write_yaml).blocking(Capability::FsWrite),
+    HostFunction::new("log", &["message", "level?"], log),
+];
+
+impl HostFunction {
+    const fn new(
+        name: &'static str,
+        params: &'static [&'static str],
+        function: fn(&mut Dev, &Args) -> Result<Value>,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            blocking: false,
+            capability: None,
+            function,
+        }
+    }
+
+    const fn blocking(mut self, capability: Capability) -> Self {
+        self.blocking = true;
+        self.capability = Some(capability);
+        self
+    }
+
+    /// The camelCase name JavaScript exports it as.
+    pub fn js_name(&self) -> String {
+        let mut words = self.name.split('_');
+        let first = words.next().unwrap_or_default().to_string();
+        words.fold(first, |mut name, word| {
+            let mut chars = word.chars();
+            if let Some(c) = chars.next() {
+                name.extend(c.to_uppercase());
+                name.push_str(chars.as_str());
+            }
+            name
+        })
+    }
+
+    /// The parameter names without the optional marker.
+    pub fn param_names(&self) -> impl Iterator<Item = &'static str> {
+        self.params.iter().map(|param| param.trim_end_matches('?'))
+    }
+
+    pub fn required_params(&self) -> usize {
+        self.params
+            .iter()
+            .filter(|param| !param.ends_with('?'))
+            .count()
+    }
+
+    /// Call the function for a script written in `language` with its
+    /// arguments converted to JSON values.
+    pub fn call(&self, language: &str, dev: &mut Dev, mut args: Vec<Value>) -> Result<Value> {
+        // Lua passes explicit nils and JS undefined for left out arguments
+        while args.last().is_some_and(Value::is_null) {
+            args.pop();
+        }
+
+        if args.len() > self.params.len() {
+            return Err(anyhow!(
+                "{} takes at most {} arguments, got {}",
+                self.name,
+                self.params.len(),
+                args.len()
+            ));
+        }
+
+        let args = Args {
+            function: self,
+            language,
+            values: args,
+        };
+        (self.function)(dev, &args)
+    }
+}
+
+pub struct Args<'a> {
+    function: &'a HostFunction,
+    language: &'a str,
+    values: Vec<Value>,
+}
+
+impl Args<'_> {
+    fn get<T: DeserializeOwned>(&self, index: usize) -> Result<T> {
+        let value = self.values.get(index).cloned().unwrap_or_default();
+        let param = self.function.param_names().nth(index).unwrap_or_default();
+        serde_json::from_value(value).map_err(|e| anyhow!("{}: {param}: {e}", self.function.name))
+    }
+}
+
+/// Wait on `future` from a host function, these are called from plain
+/// threads as well as the runtime's own.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::block_in_place(|| handle.block_on(future))
+}
+
+fn get_version(dev: &mut Dev, _args: &Args) -> Result<Value> {
+    Ok(json!(dev.get_version()))
+}
+
+fn set_version(dev: &mut Dev, args: &Args) -> Result<Value> {
+    dev.version = args.get(0)?;
+    Ok(Value::Null)
+}
+
+fn get_dir(dev: &mut Dev, _args: &Args) -> Result<Value> {
+    Ok(json!(dev.get_dir()))
+}
+
+fn set_dir(dev: &mut Dev, args: &Args) -> Result<Value> {
+    dev.dir = args.get(0)?;
+    Ok(Value::Null)
+}
+
+fn get_work_dir(_dev: &mut Dev, _args: &Args) -> Result<Value> {
+    Ok(json!(env::current_dir()?))
+}
+
+/// get_env() returns the whole environment, get_env(key) a single value.
+fn get_env(dev: &mut Dev, args: &Args) -> Result<Value> {
+    let env = dev.get_env();
+    match args.get::<Option<String>>(0)? {
+        Some(key) => Ok(json!(env.get(&key))),
+        None => Ok(json!(env)),
+    }
+}
+
+fn set_env(dev: &mut Dev, args: &Args) -> Result<Value> {
+    dev.add_env((args.get(0)?, args.get(1)?));
+    Ok(Value::Null)
+}
+
+fn get_steps(dev: &mut Dev, _args: &Args) -> Result<Value> {
+    Ok(json!(dev.steps))
+}
+
+fn add_step(dev: &mut Dev, args: &Args) -> Result<Value> {
+    dev.steps.push(args.get(0)?);
+    Ok(Value::Null)
+}
+
+/// What the aliases this one depends on returned, as
+/// `{ alias: { exit_code, output } }`. Scripts can also read it as `dev.deps`.
+fn get_deps(dev: &mut Dev, _args: &Args) -> Result<Value> {
+    Ok(json!(dev.deps))
+}
+
+fn get_repos(dev: &mut Dev, _args: &Args) -> Result<Value> {
+    let mut repos = dev
+        .get_config()
+        .get_repos()
+        .collect::<Vec<&GitRepository>>();
+    repos.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(json!(repos))
+}
+
+fn get_repo(dev: &mut Dev, args: &Args) -> Result<Value> {
+    let name: String = args.get(0)?;
+    Ok(json!(dev.get_config().get_repo(&name)))
+}
+
+fn get_alias(dev: &mut Dev, args: &Args) -> Result<Value> {
+    let name: String = args.get(0)?;
+    let alias = dev.get_config().get_run(&name).map(|runref| {
+        json!({
+            "name": name,
+            "file": runref.file,
+            "command": runref.command,
+            "filetype": runref.filetype.as_ref().map(|lang| lang.get_name()),
+            "deps": runref.dependencies.clone().unwrap_or_default(),
+            "env": runref.environment.clone().unwrap_or_default(),
+        })
+    });
+    Ok(json!(alias))
+}
+
+/// run(alias, args?) runs another alias, same as `dev <alias>`, and returns
+/// its output.
+fn run(dev: &mut Dev, args: &Args) -> Result<Value> {
+    let alias: String = args.get(0)?;
+    let alias_args: Vec<String> = args.get::<Option<_>>(1)?.unwrap_or_default();
+    let alias_args = alias_args.iter().map(|arg| arg.as_str()).collect();
+
+    let status = block_on(run_alias(dev.get_config(), &alias, Some(alias_args)))
+        .map_err(|e| anyhow!("{alias}: {e}"))?;
+    Ok(status.output.unwrap_or_default())
+}
+
+/// exec(command, args?) returns `{ code, stdout, stderr }`.
+fn exec(dev: &mut Dev, args: &Args) -> Result<Value> {
+    let command: String = args.get(0)?;
+    let command_args: Vec<String> = args.get::<Option<_>>(1)?.unwrap_or_default();
+
+    let output = Command::new(&command)
+        .args(&command_args)
+        .envs(dev.get_env())
+        .output()
+        .map_err(|e| anyhow!("{command}: {e}"))?;
+
+    Ok(json!({
+        "code": output.status.code(),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    }))
+}
+
+fn git_fetch(_dev: &mut Dev, args: &Args) -> Result<Value> {
+    let path: String = args.get(0)?;
+    let repo = GitRepository {
+        org: None,
+        name: path.clone(),
+        url: None,
+        path: Some(path),
+    };
+
+    repo.fetch(None).map_err(|e| anyhow!("{e}"))?;
+    Ok(Value::Null)
+}
+
+fn read_yaml(_dev: &mut Dev, args: &Args) -> Result<Value> {
+    let path: PathBuf = args.get(0)?;
+    let contents = fs::read_to_string(&path).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+    Ok(serde_yaml::from_str(&contents)?)
+}
+
+fn write_yaml(_dev: &mut Dev, args: &Args) -> Result<Value> {
+    let path: PathBuf = args.get(0)?;
+    let value: Value = args.get(1)?;
+    fs::write(&path, serde_yaml::to_string(&value)?)
+        .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+    Ok(Value::Null)
+}
+
+/// log(message, level?) logs under the script's language, at info unless
+/// told otherwise.
+fn log(_dev: &mut Dev, args: &Args) -> Result<Value> {
+    let message: String = args.get(0)?;
+    let level = match args.get::<Option<String>>(1)? {
+        Some(level) => {
+            Level::from_str(&level).map_err(|_| anyhow!("log: unknown level {level}"))?
+        }
+        None => Level::Info,
+    };
+
+    log!(target: args.language, level, "{message}");
+    Ok(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn find(name: &str) -> Option<&'static HostFunction> {
+        HOST_API.iter().find(|function| function.name == name)
+    }
+
+    #[test]
+    fn js_names() {
+        assert_eq!(find("get_work_dir").unwrap().js_name(), "getWorkDir");
+        assert_eq!(find("exec").unwrap().js_name(), "exec");
+    }
+
+    #[test]
+    fn argument_errors() {
+        let mut dev = Dev::new(&Config::default());
+        let set_env = find("set_env").unwrap();
+
+        let error = set_env
+            .call("test", &mut dev, vec![json!("KEY")])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "set_env: value: invalid type: null, expected a string"
+        );
+
+        let args = vec![json!("KEY"), json!("value"), json!("extra")];
+        assert!(set_env.call("test", &mut dev, args).is_err());
+    }
+
+    /// Runs tests/host/scenario.<ext> for every runner that's built in, each
+    /// one exercises the whole host API and fails if anything differs.
+    #[cfg(any(
+        feature = "python",
+        feature = "lua",
+        feature = "javascript",
+        feature = "rhai"
+    ))]
+    async fn conformance(language: &str) {
+        use crate::runners::{Language, LanguageFunctions};
+
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let config = Config::try_from(
+            r#"
+            [run.noop]
+            filetype = "sh"
+            command = "true"
+
+            [repos.alpha]
+            name = "alpha"
+            path = "tests/alpha"
+            "#
+            .to_string(),
+        )
+        .unwrap();
+
+        let lang = Language::try_from(language).unwrap();
+        let yaml = env::temp_dir().join(format!("dev-host-{language}.yaml"));
+        let mut dev = Dev::new(&config);
+        dev.add_env(("HOST_YAML".to_string(), yaml.display().to_string()));
+
+        let file = format!("{dir}/tests/host/scenario{}", lang.get_extension());
+        let result = lang.run_file(dev, &file, vec![]).await;
+        let _ = fs::remove_file(yaml);
+
+        if let Err(e) = result {
+            panic!("{language}: {e}");
+        }
+    }
+
+    #[cfg(feature = "python")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn python_conformance() {
+        conformance("py").await;
+    }
+
+    #[cfg(feature = "lua")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lua_conformance() {
+        conformance("lua").await;
+    }
+
+    #[cfg(feature = "javascript")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn javascript_conformance() {
+        conformance("js").await;
+    }
+
+    #[cfg(feature = "rhai")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rhai_conformance() {
+        conformance("rhai").await;
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs;
+
+use serde::Serialize;
+
+use super::{
+    errors::{throw_error, throw_type_error},
+    event_loop, LOG_TARGET,
+};
+use crate::runners::{Capability, Dev, HostFunction, Sandbox, HOST_API};
+
+// The build state lives in an isolate slot rather than on the global object,
+// so scripts can only reach it through these exports: the host API under its
+// camelCase names plus a couple of file helpers
+const FILE_EXPORTS: &[&str] = &["readFile", "writeFile"];
+
+fn export_names() -> impl Iterator<Item = String> {
+    HOST_API
+        .iter()
+        .map(HostFunction::js_name)
+        .chain(FILE_EXPORTS.iter().map(|name| name.to_string()))
+        .chain(std::iter::once("deps".to_string()))
+}
+
+/// Create the synthetic `dev` module, its exports are filled in when the
+/// module is evaluated.
+pub(super) fn create<'a>(scope: &mut v8::HandleScope<'a>) -> Option<v8::Local<'a, v8::Module>> {
+    let module_name = v8::String::new(scope, "dev")?;
+    let export_names = export_names()
+        .map(|name| v8::String::new(scope, &name))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(v8::Module::create_synthetic_module(
+        scope,
+        module_name,
+        &export_names,
+        evaluate_module,
+    ))
+}
+
+fn export<'a>(scope: &mut v8::HandleScope<'a>, name: &str) -> Option<v8::Local<'a, v8::Function>> {
+    if let Some(index) = HOST_API
+        .iter()
+        .position(|function| function.js_name() == name)
+    {
+        // Every host function shares one callback, the data says which it is
+        let index = v8::Integer::new(scope, index as i32);
+        return v8::Function::builder(call_host)
+            .data(index.into())
+            .build(scope);
+    }
+
+    match name {
+        "readFile" => v8::Function::new(scope, read_file),
+        "writeFile" => v8::Function::new(scope, write_file),
+        _ => None,
+    }
+}
+
+#[inline]
+fn evaluate_module<'a>(
+    context: v8::Local<'a, v8::Context>,
+    module: v8::Local<v8::Module>,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let scope = &mut unsafe { v8::CallbackScope::new(context) };
+
+    for name in export_names() {
+        let value = match name.as_str() {
+            // What the alias's dependencies returned, fixed for the run
+            "deps" => {
+                let deps = scope.get_slot::<Dev>()?.deps.clone();
+                serde_v8::to_v8(scope, deps).ok()?
+            }
+            name => export(scope, name)?.into(),
+        };
+        let key = v8::String::new(scope, &name)?;
+        module.set_synthetic_module_export(scope, key, value)?;
+    }
+
+    // Seems like it doesn't matter what we return
+    // here it just has to be something
+    let obj = v8::Object::new(scope);
+    Some(obj.into())
+}
+
+fn string_arg(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    index: i32,
+    name: &str,
+) -> Option<String> {
+    let value = args.get(index);
+    if value.is_string() {
+        Some(value.to_rust_string_lossy(scope))
+    } else {
+        throw_type_error(scope, &format!("{name} must be a string"));
+        None
+    }
+}
+
+/// Check `capability` against the alias sandbox, throwing in the script
+/// when it isn't allowed. Only sandboxed aliases have one in the isolate.
+fn allowed(scope: &mut v8::HandleScope, capability: Capability) -> bool {
+    let allowed = scope
+        .get_slot::<Sandbox>()
+        .map_or(true, |sandbox| sandbox.allows(capability));
+
+    if !allowed {
+        let message = format!("Permission denied: {capability} is not allowed for this alias");
+        throw_error(scope, &message);
+    }
+
+    allowed
+}
+
+fn set_return<T: Serialize>(scope: &mut v8::HandleScope, retval: &mut v8::ReturnValue, value: T) {
+    match serde_v8::to_v8(scope, value) {
+        Ok(value) => retval.set(value),
+        Err(e) => throw_type_error(scope, &e.to_string()),
+    }
+}
+
+/// The callback behind every host function export. Blocking functions run on
+/// their own thread with a copy of the build state and return a promise.
+#[inline]
+fn call_host(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let index = args.data().int32_value(scope).unwrap_or_default();
+    let Some(function) = HOST_API.get(index as usize) else {
+        return;
+    };
+
+    if let Some(capability) = function.capability {
+        if !allowed(scope, capability) {
+            return;
+        }
+    }
+
+    let mut values = Vec::new();
+    for i in 0..args.length() {
+        match serde_v8::from_v8::<serde_json::Value>(scope, args.get(i)) {
+            Ok(value) => values.push(value),
+            Err(e) => return throw_type_error(scope, &format!("{}: {e}", function.js_name())),
+        }
+    }
+
+    if function.blocking {
+        let Some(mut dev) = scope.get_slot::<Dev>().cloned() else {
+            return;
+        };
+        let promise = event_loop::spawn_op(scope, move || {
+            function
+                .call(LOG_TARGET, &mut dev, values)
+                .map_err(|e| e.to_string())
+        });
+
+        if let Some(promise) = promise {
+            retval.set(promise.into());
+        }
+        return;
+    }
+
+    let result = match scope.get_slot_mut::<Dev>() {
+        Some(dev) => function.call(LOG_TARGET, dev, values),
+        None => return,
+    };
+
+    match result {
+        Ok(value) => set_return(scope, &mut retval, value),
+        Err(e) => throw_error(scope, &e.to_string()),
+    }
+}
+
+#[inline]
+fn read_file(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    if !allowed(scope, Capability::FsRead) {
+        return;
+    }
+
+    let path = match string_arg(scope, &args, 0, "path") {
+        Some(path) => path,
+        None => return,
+    };
+
+    let promise = event_loop::spawn_op(scope, move || {
+        fs::read_to_string(&path)
+            .map(serde_json::Value::String)
+            .map_err(|e| format!("{path}: {e}"))
+    });
+
+    if let Some(promise) = promise {
+        retval.set(promise.into());
+    }
+}
+
+#[inline]
+fn write_file(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    if !allowed(scope, Capability::FsWrite) {
+        return;
+    }
+
+    let path = match string_arg(scope, &args, 0, "path") {
+        Some(path) => path,
+        None => return,
+    };
+    let contents = match string_arg(scope, &args, 1, "contents") {
+        Some(contents) => contents,
+        None => return,
+    };
+
+    let promise = event_loop::spawn_op(scope, move || {
+        fs::write(&path, contents)
+            .map(|_| serde_json::Value::Null)
+            .map_err(|e| format!("{path}: {e}"))
+    });
+
+    if let Some(promise) = promise {
+        retval.set(promise.into());
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use anyhow::anyhow;
+
+use crate::runners::RunError;
+
+/// Format an exception the same way for every failure: `file:line: message`,
+/// the offending source line with the failing range underlined, and the
+/// frames of the stack trace when the exception carries one.
+pub(super) fn report(
+    scope: &mut v8::HandleScope,
+    exception: v8::Local<v8::Value>,
+    message: Option<v8::Local<v8::Message>>,
+) -> String {
+    let exception_string = exception.to_rust_string_lossy(scope);
+    let message = message.unwrap_or_else(|| v8::Exception::create_message(scope, exception));
+    let mut report = String::new();
+
+    let filename = message
+        .get_script_resource_name(scope)
+        .map_or_else(|| "(unknown)".into(), |s| s.to_rust_string_lossy(scope));
+    let line_number = message.get_line_number(scope).unwrap_or_default();
+
+    let _ = writeln!(report, "{}:{}: {}", filename, line_number, exception_string);
+
+    if let Some(source_line) = message.get_source_line(scope) {
+        let start_column = message.get_start_column();
+        let end_column = message.get_end_column().max(start_column + 1);
+
+        let _ = writeln!(report, "{}", source_line.to_rust_string_lossy(scope));
+        let _ = writeln!(
+            report,
+            "{}{}",
+            " ".repeat(start_column),
+            "^".repeat(end_column - start_column)
+        );
+    }
+
+    if let Some(stack_trace) = stack_trace(scope, exception) {
+        let _ = writeln!(report, "{}", stack_trace);
+    }
+
+    report.trim_end().to_string()
+}
+
+/// The frames of the exception's `stack`.
+fn stack_trace(scope: &mut v8::HandleScope, exception: v8::Local<v8::Value>) -> Option<String> {
+    if !exception.is_native_error() {
+        return None;
+    }
+
+    let exception = exception.to_object(scope)?;
+    let key = v8::String::new(scope, "stack")?;
+    let stack = exception.get(scope, key.into())?;
+
+    if !stack.is_string() {
+        return None;
+    }
+
+    // The stack starts with the message, already at the top of the report,
+    // which can span lines itself so keep only the frames
+    let frames = stack
+        .to_rust_string_lossy(scope)
+        .lines()
+        .filter(|line| line.trim_start().starts_with("at "))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (!frames.is_empty()).then_some(frames)
+}
+
+pub(super) fn throw_error(scope: &mut v8::HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    scope.throw_exception(exception);
+}
+
+pub(super) fn throw_type_error(scope: &mut v8::HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::type_error(scope, message);
+    scope.throw_exception(exception);
+}
+
+/// Wrap the report for `exception` in a `RunError` so callers get the
+/// location of the failure rather than just the exception text.
+pub(super) fn error_from_exception(
+    scope: &mut v8::HandleScope,
+    exception: v8::Local<v8::Value>,
+    message: Option<v8::Local<v8::Message>>,
+) -> anyhow::Error {
+    anyhow!(RunError {
+        exit_code: None,
+        message: Some(report(scope, exception, message)),
+    })
+}
+
+/// Same as `error_from_exception` for whatever `try_catch` caught, falling
+/// back to `context` when V8 failed without throwing.
+pub(super) fn error_from_try_catch(
+    try_catch: &mut v8::TryCatch<v8::HandleScope>,
+    context: &str,
+) -> anyhow::Error {
+    let message = try_catch.message();
+    match try_catch.exception() {
+        Some(exception) => error_from_exception(try_catch, exception, message),
+        None => anyhow!(RunError {
+            exit_code: None,
+            message: Some(context.to_string()),
+        }),
+    }
+}
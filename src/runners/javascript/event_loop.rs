@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+use super::errors;
+
+type OpResult = Result<Value, String>;
+
+struct Timer {
+    id: usize,
+    deadline: Instant,
+    callback: v8::Global<v8::Function>,
+    args: Vec<v8::Global<v8::Value>>,
+}
+
+enum Work {
+    Op(v8::Global<v8::PromiseResolver>, OpResult),
+    Timer(Timer),
+}
+
+/// Host operations that are still running and timers waiting to fire, keyed
+/// by the id handed out when they were created. Lives in an isolate slot so
+/// function callbacks can queue work without any access to the runner itself.
+pub(super) struct EventLoop {
+    next_id: usize,
+    pending: HashMap<usize, v8::Global<v8::PromiseResolver>>,
+    timers: Vec<Timer>,
+    deadline: Option<Instant>,
+    sender: Sender<(usize, OpResult)>,
+    receiver: Receiver<(usize, OpResult)>,
+}
+
+impl EventLoop {
+    pub(super) fn new(deadline: Option<Instant>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            next_id: 0,
+            pending: HashMap::new(),
+            timers: Vec::new(),
+            deadline,
+            sender,
+            receiver,
+        }
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn finish_op(&mut self, (id, result): (usize, OpResult)) -> Result<Work, anyhow::Error> {
+        let resolver = self
+            .pending
+            .remove(&id)
+            .ok_or(anyhow!("Unknown host operation {id}"))?;
+        Ok(Work::Op(resolver, result))
+    }
+
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Block until either a host operation finishes or the earliest timer
+    /// is due, whichever comes first. Gives up once the deadline passes.
+    fn next_work(&mut self) -> Result<Option<Work>, anyhow::Error> {
+        let next_timer = self
+            .timers
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, timer)| timer.deadline)
+            .map(|(index, timer)| (index, timer.deadline));
+
+        if next_timer.is_none() && self.pending.is_empty() {
+            return Ok(None);
+        }
+
+        let wake_at = match (next_timer.map(|(_, due)| due), self.deadline) {
+            (Some(due), Some(deadline)) => Some(due.min(deadline)),
+            (due, deadline) => due.or(deadline),
+        };
+
+        let finished = match wake_at {
+            Some(wake_at) => {
+                let wait = wake_at.saturating_duration_since(Instant::now());
+                if self.pending.is_empty() {
+                    thread::sleep(wait);
+                    None
+                } else {
+                    match self.receiver.recv_timeout(wait) {
+                        Ok(finished) => Some(finished),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            None => Some(self.receiver.recv()?),
+        };
+
+        if let Some(finished) = finished {
+            return self.finish_op(finished).map(Some);
+        }
+
+        match next_timer {
+            Some((index, _)) if !self.past_deadline() => {
+                Ok(Some(Work::Timer(self.timers.remove(index))))
+            }
+            _ => Err(anyhow!("Deadline passed with work still pending")),
+        }
+    }
+}
+
+/// Run `op` on its own thread and return a promise that settles with its
+/// result the next time the event loop is polled.
+pub(super) fn spawn_op<'s, F>(
+    scope: &mut v8::HandleScope<'s>,
+    op: F,
+) -> Option<v8::Local<'s, v8::Promise>>
+where
+    F: FnOnce() -> OpResult + Send + 'static,
+{
+    let resolver = v8::PromiseResolver::new(scope)?;
+    let promise = resolver.get_promise(scope);
+    let resolver = v8::Global::new(scope, resolver);
+
+    let event_loop = scope.get_slot_mut::<EventLoop>()?;
+    let id = event_loop.next_id();
+    event_loop.pending.insert(id, resolver);
+
+    // Ops can need the runtime themselves, running another alias does
+    let runtime = tokio::runtime::Handle::try_current().ok();
+    let sender = event_loop.sender.clone();
+    thread::spawn(move || {
+        let _runtime = runtime.as_ref().map(|runtime| runtime.enter());
+        let _ = sender.send((id, op()));
+    });
+
+    Some(promise)
+}
+
+/// Schedule `callback` to be called with `args` once `delay` has passed,
+/// returning the id that can be handed to `clear_timer`.
+pub(super) fn add_timer(
+    scope: &mut v8::HandleScope,
+    callback: v8::Local<v8::Function>,
+    delay: Duration,
+    args: Vec<v8::Local<v8::Value>>,
+) -> Option<usize> {
+    let callback = v8::Global::new(scope, callback);
+    let args = args
+        .into_iter()
+        .map(|arg| v8::Global::new(scope, arg))
+        .collect();
+
+    let event_loop = scope.get_slot_mut::<EventLoop>()?;
+    let id = event_loop.next_id();
+    event_loop.timers.push(Timer {
+        id,
+        deadline: Instant::now() + delay,
+        callback,
+        args,
+    });
+
+    Some(id)
+}
+
+pub(super) fn clear_timer(scope: &mut v8::HandleScope, id: usize) {
+    if let Some(event_loop) = scope.get_slot_mut::<EventLoop>() {
+        event_loop.timers.retain(|timer| timer.id != id);
+    }
+}
+
+/// Drive microtasks, timers and host operations until `promise` settles and
+/// nothing is left to run, the way Node and Deno keep a script alive for a
+/// timer or read it never awaited. Returns the fulfilled value or an error
+/// carrying the rejection reason, a rejection is reported straight away.
+pub(super) fn run_until_settled<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    promise: v8::Local<'s, v8::Promise>,
+) -> Result<v8::Local<'s, v8::Value>, anyhow::Error> {
+    loop {
+        scope.perform_microtask_checkpoint();
+
+        if let v8::PromiseState::Rejected = promise.state() {
+            let reason = promise.result(scope);
+            return Err(errors::error_from_exception(scope, reason, None));
+        }
+
+        if !poll(scope)? {
+            break;
+        }
+    }
+
+    match promise.state() {
+        v8::PromiseState::Fulfilled => Ok(promise.result(scope)),
+        v8::PromiseState::Rejected => {
+            let reason = promise.result(scope);
+            Err(errors::error_from_exception(scope, reason, None))
+        }
+        v8::PromiseState::Pending => Err(anyhow!(
+            "Promise is still pending but there is no work left to run"
+        )),
+    }
+}
+
+/// Run microtasks, timers and host operations until there are none left.
+pub(super) fn run_until_idle(scope: &mut v8::HandleScope) -> Result<(), anyhow::Error> {
+    loop {
+        scope.perform_microtask_checkpoint();
+        if !poll(scope)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Wait for the next host operation or timer and run it. Returns false when
+/// there is nothing left that could make progress.
+fn poll(scope: &mut v8::HandleScope) -> Result<bool, anyhow::Error> {
+    let work = scope
+        .get_slot_mut::<EventLoop>()
+        .ok_or(anyhow!("Event loop is not installed in the isolate"))?
+        .next_work()?;
+
+    match work {
+        None => return Ok(false),
+        Some(Work::Op(resolver, result)) => {
+            let resolver = v8::Local::new(scope, resolver);
+            match result {
+                Ok(value) => {
+                    let value = serde_v8::to_v8(scope, value)?;
+                    resolver.resolve(scope, value);
+                }
+                Err(message) => {
+                    let message = v8::String::new(scope, &message)
+                        .ok_or(anyhow!("Failed to create error message"))?;
+                    let exception = v8::Exception::error(scope, message);
+                    resolver.reject(scope, exception);
+                }
+            }
+        }
+        Some(Work::Timer(timer)) => {
+            let callback = v8::Local::new(scope, timer.callback);
+            let args = timer
+                .args
+                .into_iter()
+                .map(|arg| v8::Local::new(scope, arg))
+                .collect::<Vec<_>>();
+            let recv: v8::Local<v8::Value> = v8::undefined(scope).into();
+
+            let tc_scope = &mut v8::TryCatch::new(scope);
+            if callback.call(tc_scope, recv, &args).is_none() {
+                return Err(errors::error_from_try_catch(
+                    tc_scope,
+                    "Timer callback failed",
+                ));
+            }
+        }
+    }
+
+    Ok(true)
+}
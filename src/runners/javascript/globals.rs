@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use log::{debug, error, warn};
+
+use super::{errors::throw_type_error, event_loop, LOG_TARGET};
+
+/// Install the small set of web-style globals scripts expect to exist:
+/// `console`, `setTimeout`/`clearTimeout` and `queueMicrotask`.
+pub(super) fn install(scope: &mut v8::HandleScope, global: v8::Local<v8::Object>) -> Option<()> {
+    let console = v8::Object::new(scope);
+    set_function(scope, console, "log", console_log)?;
+    set_function(scope, console, "info", console_log)?;
+    set_function(scope, console, "warn", console_warn)?;
+    set_function(scope, console, "error", console_error)?;
+
+    let console_key = v8::String::new(scope, "console")?;
+    global.set(scope, console_key.into(), console.into())?;
+
+    set_function(scope, global, "setTimeout", set_timeout)?;
+    set_function(scope, global, "clearTimeout", clear_timeout)?;
+    set_function(scope, global, "queueMicrotask", queue_microtask)?;
+
+    Some(())
+}
+
+fn set_function(
+    scope: &mut v8::HandleScope,
+    target: v8::Local<v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) -> Option<()> {
+    let key = v8::String::new(scope, name)?;
+    let function = v8::Function::new(scope, callback)?;
+    target.set(scope, key.into(), function.into())?;
+    Some(())
+}
+
+fn format_args(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments) -> String {
+    (0..args.length())
+        .map(|i| format_value(scope, args.get(i)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn format_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    // Plain objects and arrays print as JSON, everything else (including
+    // errors, whose JSON form is just `{}`) uses its string conversion
+    if value.is_object() && !value.is_function() && !value.is_native_error() {
+        if let Some(json) = v8::json::stringify(scope, value) {
+            return json.to_rust_string_lossy(scope);
+        }
+    }
+    value.to_rust_string_lossy(scope)
+}
+
+// The logger already writes warnings and errors to stdout, console.log is
+// printed directly and only recorded in the log at debug level so it doesn't
+// show up twice
+#[inline]
+fn console_log(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _retval: v8::ReturnValue,
+) {
+    let message = format_args(scope, &args);
+    debug!(target: LOG_TARGET, "{}", message);
+    println!("{}", message);
+}
+
+#[inline]
+fn console_warn(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _retval: v8::ReturnValue,
+) {
+    let message = format_args(scope, &args);
+    warn!(target: LOG_TARGET, "{}", message);
+}
+
+#[inline]
+fn console_error(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _retval: v8::ReturnValue,
+) {
+    let message = format_args(scope, &args);
+    error!(target: LOG_TARGET, "{}", message);
+}
+
+#[inline]
+fn set_timeout(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let callback = match v8::Local::<v8::Function>::try_from(args.get(0)) {
+        Ok(callback) => callback,
+        Err(_) => return throw_type_error(scope, "setTimeout callback must be a function"),
+    };
+
+    let delay = args.get(1).number_value(scope).unwrap_or(0.0).max(0.0);
+    let callback_args = (2..args.length()).map(|i| args.get(i)).collect();
+
+    let id = event_loop::add_timer(
+        scope,
+        callback,
+        Duration::from_millis(delay as u64),
+        callback_args,
+    );
+
+    if let Some(id) = id {
+        let id = v8::Number::new(scope, id as f64);
+        retval.set(id.into());
+    }
+}
+
+#[inline]
+fn clear_timeout(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _retval: v8::ReturnValue,
+) {
+    if let Some(id) = args.get(0).number_value(scope) {
+        event_loop::clear_timer(scope, id as usize);
+    }
+}
+
+#[inline]
+fn queue_microtask(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _retval: v8::ReturnValue,
+) {
+    match v8::Local::<v8::Function>::try_from(args.get(0)) {
+        Ok(callback) => scope.enqueue_microtask(callback),
+        Err(_) => throw_type_error(scope, "queueMicrotask callback must be a function"),
+    }
+}
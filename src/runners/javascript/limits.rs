@@ -0,0 +1,132 @@
+use std::{
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::runners::{RunError, Sandbox};
+
+struct HeapLimit {
+    handle: v8::IsolateHandle,
+    exceeded: Arc<AtomicBool>,
+}
+
+extern "C" fn near_heap_limit(
+    data: *mut c_void,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+) -> usize {
+    // SAFETY: data points at the HeapLimit boxed in the isolate's slots,
+    // which is only dropped along with the isolate itself
+    let heap_limit = unsafe { &*(data as *const HeapLimit) };
+    heap_limit.exceeded.store(true, Ordering::SeqCst);
+    heap_limit.handle.terminate_execution();
+
+    // Give V8 enough room to unwind the terminated script, otherwise it
+    // aborts the whole process with an out of memory error
+    current_heap_limit * 2
+}
+
+/// Heap and wall-clock limits applied to a single isolate. Both stop the
+/// script with `terminate_execution`, `exceeded` tells the runner which one
+/// fired so it can report it instead of the termination itself.
+pub(super) struct Limits {
+    heap_mb: Option<usize>,
+    timeout_ms: Option<u64>,
+    deadline: Option<Instant>,
+    heap_exceeded: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    // Dropping the sender wakes the watchdog thread and lets it exit
+    _watchdog: Option<mpsc::Sender<()>>,
+}
+
+impl Limits {
+    pub(super) fn create_params(sandbox: &Sandbox) -> v8::CreateParams {
+        let params = v8::CreateParams::default();
+        match sandbox.heap_mb {
+            Some(heap_mb) => params.heap_limits(0, heap_mb * 1024 * 1024),
+            None => params,
+        }
+    }
+
+    pub(super) fn install(isolate: &mut v8::OwnedIsolate, sandbox: &Sandbox) -> Self {
+        let heap_exceeded = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+
+        if sandbox.heap_mb.is_some() {
+            let heap_limit = Box::new(HeapLimit {
+                handle: isolate.thread_safe_handle(),
+                exceeded: heap_exceeded.clone(),
+            });
+            let data = &*heap_limit as *const HeapLimit as *mut c_void;
+            isolate.add_near_heap_limit_callback(near_heap_limit, data);
+            isolate.set_slot(heap_limit);
+        }
+
+        let mut deadline = None;
+        let mut watchdog = None;
+        if let Some(timeout_ms) = sandbox.timeout_ms {
+            let timeout = Duration::from_millis(timeout_ms);
+            let handle = isolate.thread_safe_handle();
+            let fired = timed_out.clone();
+            let (sender, receiver) = mpsc::channel::<()>();
+
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+                    fired.store(true, Ordering::SeqCst);
+                    handle.terminate_execution();
+                }
+            });
+
+            deadline = Some(Instant::now() + timeout);
+            watchdog = Some(sender);
+        }
+
+        Self {
+            heap_mb: sandbox.heap_mb,
+            timeout_ms: sandbox.timeout_ms,
+            deadline,
+            heap_exceeded,
+            timed_out,
+            _watchdog: watchdog,
+        }
+    }
+
+    /// The point after which the event loop should stop waiting on
+    /// timers and host operations.
+    pub(super) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub(super) fn exceeded(&self) -> Option<anyhow::Error> {
+        let message = if self.heap_exceeded.load(Ordering::SeqCst) {
+            format!(
+                "Script exceeded the heap limit of {} MB",
+                self.heap_mb.unwrap_or_default()
+            )
+        } else if self.timed_out.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            format!(
+                "Script did not finish within {} ms",
+                self.timeout_ms.unwrap_or_default()
+            )
+        } else {
+            return None;
+        };
+
+        Some(anyhow!(RunError {
+            exit_code: None,
+            message: Some(message),
+        }))
+    }
+}
@@ -6,26 +6,51 @@ use log::{debug, error, info};
 use std::{fs, path::Path, process::Command};
 
 #[cfg(feature = "javascript")]
-use v8::Module;
+use super::log_output;
+use super::{dev::Dev, language, language::LanguageFunctions, RunStatus, Sandbox};
 
-use super::{dev::Dev, language, language::LanguageFunctions, RunStatus};
+#[cfg(feature = "javascript")]
+mod dev_module;
+#[cfg(feature = "javascript")]
+mod errors;
+#[cfg(feature = "javascript")]
+mod event_loop;
+#[cfg(feature = "javascript")]
+mod globals;
+#[cfg(feature = "javascript")]
+mod limits;
 
 #[cfg(feature = "javascript")]
 static LOG_TARGET: &str = "javascript";
 
+#[cfg(feature = "javascript")]
+static V8_INIT: std::sync::Once = std::sync::Once::new();
+
 #[derive(Debug, Clone)]
-pub struct JavaScriptLanguage {}
+pub struct JavaScriptLanguage {
+    /// Aliases without a sandbox run unrestricted
+    #[cfg_attr(not(feature = "javascript"), allow(dead_code))]
+    sandbox: Option<Sandbox>,
+}
 
 impl JavaScriptLanguage {
     pub fn new() -> Self {
-        Self {}
+        Self { sandbox: None }
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(sandbox);
     }
 
     #[cfg(feature = "javascript")]
     fn init(&self) -> Result<(), anyhow::Error> {
-        let platform = v8::new_default_platform(0, false).make_shared();
-        v8::V8::initialize_platform(platform);
-        v8::V8::initialize();
+        // V8 panics if the platform is initialized twice, which happens as
+        // soon as more than one script runs in the same process
+        V8_INIT.call_once(|| {
+            let platform = v8::new_default_platform(0, false).make_shared();
+            v8::V8::initialize_platform(platform);
+            v8::V8::initialize();
+        });
         Ok(())
     }
 }
@@ -81,49 +106,45 @@ impl JavaScriptLanguage {
     ) -> Result<RunStatus, anyhow::Error> {
         self.init()?;
 
-        let isolate = &mut v8::Isolate::new(Default::default());
+        let sandbox = self.sandbox.clone().unwrap_or_default();
+        let isolate = &mut v8::Isolate::new(limits::Limits::create_params(&sandbox));
+        let limits = limits::Limits::install(isolate, &sandbox);
+        isolate.set_microtasks_policy(v8::MicrotasksPolicy::Explicit);
+        isolate.set_slot(event_loop::EventLoop::new(limits.deadline()));
+        if let Some(sandbox) = &self.sandbox {
+            isolate.set_slot(sandbox.clone());
+        }
+        let initial = serde_json::to_value(&dev)?;
+        isolate.set_slot(dev);
+
         let handle_scope = &mut v8::HandleScope::new(isolate);
         let context = v8::Context::new(handle_scope, Default::default());
         let scope = &mut v8::ContextScope::new(handle_scope, context);
         let global = context.global(scope);
+        globals::install(scope, global).ok_or(anyhow!("Failed to install globals"))?;
 
-        {
-            let key = v8::String::new(scope, "Dev").unwrap();
-            let value = v8::External::new(scope, &dev as *const _ as *mut std::ffi::c_void);
-            global.set(scope, key.into(), value.into());
-
-            let maybe_module = load_file(file, scope)?;
-            let tc_scope = &mut v8::TryCatch::new(scope);
-
-            ensure_module_instantiated(tc_scope, maybe_module)
-                .ok_or(anyhow!("Failed to ensure module is instantiated"))?;
-
-            maybe_module
-                .evaluate(tc_scope)
-                .ok_or(anyhow!("Failed to evaluate module"))?;
+        // A script stopped by one of the sandbox limits fails with whatever
+        // termination looked like at that point, report the limit instead
+        let output = match evaluate(scope, file) {
+            Ok(output) => output,
+            Err(e) => return Err(limits.exceeded().unwrap_or(e)),
+        };
 
-            if tc_scope.has_caught() {
-                let exception = tc_scope.exception().unwrap();
-                return Err(anyhow::anyhow!(exception.to_rust_string_lossy(tc_scope)));
-            }
-
-            let module_namespace = maybe_module
-                .get_module_namespace()
-                .to_object(tc_scope)
-                .ok_or(anyhow!("Failed to convert module namespace to object"))?;
-
-            let default_key = v8::String::new(tc_scope, "default")
-                .ok_or(anyhow!("Failed to create default key string"))?;
-            let default_export = module_namespace
-                .get(tc_scope, default_key.into())
-                .ok_or(anyhow!("Failed to get default export"))?;
-
-            match serde_v8::from_v8::<Dev>(tc_scope, default_export) {
-                Ok(dev) => debug!(target: LOG_TARGET, "{:?}", dev),
-                Err(e) => error!(target: LOG_TARGET, "Error deserializing: {:?}", e),
-            }
+        if let Some(dev) = scope.get_slot::<Dev>() {
+            debug!(target: LOG_TARGET, "final state: {:?}", dev);
         }
 
+        // Without a default export, the state the script changed through the
+        // dev module's setters is what it hands back
+        let output = match output {
+            Some(output) => Some(output),
+            None => scope
+                .get_slot::<Dev>()
+                .map(serde_json::to_value)
+                .transpose()?
+                .filter(|state| state != &initial),
+        };
+
         // unsafe {
         //     v8::V8::dispose();
         // }
@@ -132,6 +153,7 @@ impl JavaScriptLanguage {
         Ok(RunStatus {
             exit_code: Some(0),
             message: None,
+            output,
         })
     }
 
@@ -142,7 +164,8 @@ impl JavaScriptLanguage {
         let handle_scope = &mut v8::HandleScope::new(isolate);
         let context = v8::Context::new(handle_scope, Default::default());
         let scope = &mut v8::ContextScope::new(handle_scope, context);
-        load_file(file, scope)?;
+        let tc_scope = &mut v8::TryCatch::new(scope);
+        load_file(file, tc_scope)?;
         Ok(())
     }
 
@@ -154,26 +177,92 @@ impl JavaScriptLanguage {
         self.init()?;
 
         let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+        isolate.set_slot(event_loop::EventLoop::new(None));
+
         let handle_scope = &mut v8::HandleScope::new(isolate);
 
         let context = v8::Context::new(handle_scope, Default::default());
 
         let context_scope = &mut v8::ContextScope::new(handle_scope, context);
         let scope = &mut v8::HandleScope::new(context_scope);
+        let global = context.global(scope);
+        globals::install(scope, global).ok_or(anyhow!("Failed to install globals"))?;
 
         run_shell(scope)?;
 
         Ok(RunStatus {
             exit_code: Some(0),
             message: None,
+            output: None,
         })
     }
 }
 
+#[cfg(feature = "javascript")]
+fn evaluate(scope: &mut v8::HandleScope, file: &str) -> Result<Option<serde_json::Value>, Error> {
+    let tc_scope = &mut v8::TryCatch::new(scope);
+
+    let maybe_module = load_file(file, tc_scope)?;
+
+    if ensure_module_instantiated(tc_scope, maybe_module).is_none() {
+        return Err(errors::error_from_try_catch(
+            tc_scope,
+            "Failed to ensure module is instantiated",
+        ));
+    }
+
+    let evaluation = match maybe_module.evaluate(tc_scope) {
+        Some(evaluation) if !tc_scope.has_caught() => evaluation,
+        _ => {
+            return Err(errors::error_from_try_catch(
+                tc_scope,
+                "Failed to evaluate module",
+            ))
+        }
+    };
+
+    // With top-level await the module body may still be running,
+    // evaluation hands back a promise for its completion
+    if let Ok(evaluation) = v8::Local::<v8::Promise>::try_from(evaluation) {
+        event_loop::run_until_settled(tc_scope, evaluation)?;
+    }
+
+    let module_namespace = maybe_module
+        .get_module_namespace()
+        .to_object(tc_scope)
+        .ok_or(anyhow!("Failed to convert module namespace to object"))?;
+
+    let default_key = v8::String::new(tc_scope, "default")
+        .ok_or(anyhow!("Failed to create default key string"))?;
+    let default_export = module_namespace
+        .get(tc_scope, default_key.into())
+        .ok_or(anyhow!("Failed to get default export"))?;
+
+    let default_export = match v8::Local::<v8::Promise>::try_from(default_export) {
+        Ok(promise) => event_loop::run_until_settled(tc_scope, promise)?,
+        Err(_) => default_export,
+    };
+
+    if default_export.is_undefined() {
+        return Ok(None);
+    }
+
+    match serde_v8::from_v8::<serde_json::Value>(tc_scope, default_export) {
+        Ok(output) => {
+            log_output(LOG_TARGET, &output);
+            Ok(Some(output))
+        }
+        Err(e) => {
+            error!(target: LOG_TARGET, "Error deserializing: {:?}", e);
+            Ok(None)
+        }
+    }
+}
+
 #[cfg(feature = "javascript")]
 fn load_file<'a>(
     file: &str,
-    scope: &mut v8::HandleScope<'a>,
+    scope: &mut v8::TryCatch<v8::HandleScope<'a>>,
 ) -> Result<v8::Local<'a, v8::Module>, Error> {
     let file_contents = fs::read_to_string(Path::new(file))?;
     let code = v8::String::new(scope, &file_contents).ok_or(anyhow!("Failed to create code"))?;
@@ -194,36 +283,7 @@ fn load_file<'a>(
     let mut source = v8::script_compiler::Source::new(code, Some(&origin));
     let maybe_module = v8::script_compiler::compile_module(scope, &mut source);
 
-    maybe_module.ok_or(anyhow!("Failed to compile module"))
-}
-
-#[inline]
-#[cfg(feature = "javascript")]
-fn get_version(
-    scope: &mut v8::HandleScope,
-    _args: v8::FunctionCallbackArguments,
-    mut retval: v8::ReturnValue,
-) {
-    let global = scope.get_current_context().global(scope);
-    let key = v8::String::new(scope, "Dev").unwrap();
-    let value = global.get(scope, key.into()).unwrap();
-    let ext = v8::Local::<v8::External>::try_from(value).unwrap();
-    let dev = unsafe { &*(ext.value() as *const Dev) };
-    let result = v8::String::new(scope, &dev.version).unwrap();
-    retval.set(result.into());
-}
-
-#[inline]
-#[cfg(feature = "javascript")]
-fn get_work_dir(
-    scope: &mut v8::HandleScope,
-    _args: v8::FunctionCallbackArguments,
-    mut retval: v8::ReturnValue,
-) {
-    let working_dir =
-        String::from_utf8_lossy(&Command::new("pwd").output().unwrap().stdout).to_string();
-    let result = v8::String::new(scope, working_dir.as_str()).unwrap();
-    retval.set(result.into());
+    maybe_module.ok_or_else(|| errors::error_from_try_catch(scope, "Failed to compile module"))
 }
 
 #[cfg(feature = "javascript")]
@@ -255,44 +315,21 @@ fn module_callback<'a>(
     let specifier_str = specifier.to_rust_string_lossy(scope);
 
     if specifier_str == "dev" {
-        let module_name = v8::String::new(scope, "dev").unwrap();
-        let export_names = [
-            v8::String::new(scope, "getVersion").unwrap(),
-            v8::String::new(scope, "getWorkDir").unwrap(),
-        ];
-
-        let dev_module =
-            Module::create_synthetic_module(scope, module_name, &export_names, evaluate_module);
+        let dev_module = dev_module::create(scope)?;
         ensure_module_instantiated(scope, dev_module).unwrap();
         let _ = dev_module.evaluate(scope);
         Some(dev_module)
     } else {
+        // Returning without an exception pending aborts instantiation with
+        // no hint of which import was the problem
+        let message = format!("Cannot find module '{specifier_str}'");
+        let message = v8::String::new(scope, &message).unwrap();
+        let exception = v8::Exception::error(scope, message);
+        scope.throw_exception(exception);
         None
     }
 }
 
-#[inline]
-#[cfg(feature = "javascript")]
-fn evaluate_module<'a>(
-    context: v8::Local<'a, v8::Context>,
-    module: v8::Local<v8::Module>,
-) -> Option<v8::Local<'a, v8::Value>> {
-    let scope = &mut unsafe { v8::CallbackScope::new(context) };
-
-    let get_version = v8::Function::new(scope, get_version).unwrap();
-    let get_version_key = v8::String::new(scope, "getVersion").unwrap();
-    let _ = module.set_synthetic_module_export(scope, get_version_key, get_version.into());
-
-    let get_work_dir = v8::Function::new(scope, get_work_dir).unwrap();
-    let get_work_dir_key = v8::String::new(scope, "getWorkDir").unwrap();
-    let _ = module.set_synthetic_module_export(scope, get_work_dir_key, get_work_dir.into());
-
-    // Seems like it doesn't matter what we return
-    // here it just has to be something
-    let obj = v8::Object::new(scope);
-    Some(obj.into())
-}
-
 /// Process remaining command line arguments and execute files
 #[cfg(feature = "javascript")]
 fn run_shell(scope: &mut v8::HandleScope) -> Result<(), anyhow::Error> {
@@ -313,6 +350,12 @@ fn run_shell(scope: &mut v8::HandleScope) -> Result<(), anyhow::Error> {
                 }
 
                 execute_string(scope, &buf, "(shell)", true, true);
+
+                // Timers and reads started by the line run before the next
+                // prompt, the shell has no other chance to drive them
+                if let Err(e) = event_loop::run_until_idle(scope) {
+                    eprintln!("{e}");
+                }
             }
             Err(error) => println!("error: {}", error),
         }
@@ -376,70 +419,64 @@ fn execute_string(
 
 #[cfg(feature = "javascript")]
 fn report_exceptions(mut try_catch: v8::TryCatch<v8::HandleScope>) {
+    let message = try_catch.message();
     let exception = try_catch.exception().unwrap();
-    let exception_string = exception
-        .to_string(&mut try_catch)
-        .unwrap()
-        .to_rust_string_lossy(&mut try_catch);
-    let message = if let Some(message) = try_catch.message() {
-        message
-    } else {
-        eprintln!("{}", exception_string);
-        return;
-    };
+    eprintln!("{}", errors::report(&mut try_catch, exception, message));
+}
 
-    // Print (filename):(line number): (message).
-    let filename = message
-        .get_script_resource_name(&mut try_catch)
-        .map_or_else(
-            || "(unknown)".into(),
-            |s| {
-                s.to_string(&mut try_catch)
-                    .unwrap()
-                    .to_rust_string_lossy(&mut try_catch)
-            },
-        );
-    let line_number = message.get_line_number(&mut try_catch).unwrap_or_default();
-
-    eprintln!("{}:{}: {}", filename, line_number, exception_string);
-
-    // Print line of source code.
-    let source_line = message
-        .get_source_line(&mut try_catch)
-        .map(|s| {
-            s.to_string(&mut try_catch)
-                .unwrap()
-                .to_rust_string_lossy(&mut try_catch)
-        })
-        .unwrap();
-    eprintln!("{}", source_line);
+#[cfg(all(test, feature = "javascript"))]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
 
-    // Print wavy underline (GetUnderline is deprecated).
-    let start_column = message.get_start_column();
-    let end_column = message.get_end_column();
+    async fn run(source: &str) -> Result<RunStatus, anyhow::Error> {
+        let mut file = tempfile::Builder::new().suffix(".js").tempfile()?;
+        file.write_all(source.as_bytes())?;
 
-    for _ in 0..start_column {
-        eprint!(" ");
+        let dev = Dev::new(&Config::default());
+        let path = file.path().to_string_lossy().into_owned();
+        JavaScriptLanguage::new().run_file(dev, &path, vec![]).await
     }
 
-    for _ in start_column..end_column {
-        eprint!("^");
+    #[tokio::test(flavor = "multi_thread")]
+    async fn top_level_await() {
+        let status = run(r#"
+            const value = await new Promise((resolve) => setTimeout(() => resolve(42), 10));
+            export default { value };
+            "#)
+        .await
+        .unwrap();
+        assert_eq!(status.output, Some(json!({ "value": 42 })));
     }
 
-    eprintln!();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn default_export_promise() {
+        let status = run(r#"
+            export default new Promise((resolve) => setTimeout(() => resolve([1, "two"]), 10));
+            "#)
+        .await
+        .unwrap();
+        assert_eq!(status.output, Some(json!([1, "two"])));
+    }
 
-    // Print stack trace
-    let stack_trace = if let Some(stack_trace) = try_catch.stack_trace() {
-        stack_trace
-    } else {
-        return;
-    };
-    let stack_trace = unsafe { v8::Local::<v8::String>::cast_unchecked(stack_trace) };
-    let stack_trace = stack_trace
-        .to_string(&mut try_catch)
-        .map(|s| s.to_rust_string_lossy(&mut try_catch));
+    #[tokio::test(flavor = "multi_thread")]
+    async fn changed_state_is_the_output() {
+        let status = run(r#"
+            import * as dev from 'dev';
+            dev.setVersion("2.0.0");
+            dev.addStep("build");
+            "#)
+        .await
+        .unwrap();
+        let output = status.output.unwrap();
+        assert_eq!(output["version"], "2.0.0");
+        assert_eq!(output["steps"], json!(["build"]));
 
-    if let Some(stack_trace) = stack_trace {
-        eprintln!("{}", stack_trace);
+        let status = run("const unchanged = true;").await.unwrap();
+        assert_eq!(status.output, None);
     }
 }
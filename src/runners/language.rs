@@ -1,11 +1,19 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
+use log::warn;
 use serde::{Deserialize, Serialize};
 
+use crate::config::RunRef;
+
+use super::external::{ExternalLanguage, LanguageCommand};
 use super::javascript::JavaScriptLanguage;
 use super::lua::LuaLanguage;
 use super::python::PythonLanguage;
-use super::shell::ShellLanguage;
+use super::rhai::RhaiLanguage;
+use super::shell::{ShellLanguage, SHELLS};
+use super::wasm::WasmLanguage;
 use super::RunStatus;
 use super::dev::Dev;
 
@@ -25,34 +33,155 @@ pub trait LanguageFunctions {
 #[enum_dispatch]
 #[derive(Clone, Debug)]
 pub enum Language {
+    External(ExternalLanguage),
     JavaScript(JavaScriptLanguage),
     Lua(LuaLanguage),
     Python(PythonLanguage),
+    Rhai(RhaiLanguage),
     Shell(ShellLanguage),
+    Wasm(WasmLanguage),
 }
 
 impl Language {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Language::External(external) => external.get_name(),
+            Language::JavaScript(_) => "javascript",
+            Language::Lua(_) => "lua",
+            Language::Python(_) => "python",
+            Language::Rhai(_) => "rhai",
+            Language::Shell(shell) => shell.get_name(),
+            Language::Wasm(_) => "wasm",
+        }
+    }
+
     pub fn get_extension(&self) -> &str {
         match self {
+            Language::External(external) => external.get_extension(),
             Language::Python(_) => ".py",
             Language::Lua(_) => ".lua",
             Language::JavaScript(_) => ".js",
-            Language::Shell(_) => ".sh",
+            Language::Rhai(_) => ".rhai",
+            Language::Shell(shell) => shell.get_extension(),
+            Language::Wasm(_) => ".wasm",
+        }
+    }
+
+    /// Apply the per-alias runner options declared on `runref`.
+    pub fn configure(&self, runref: &RunRef) -> Language {
+        let mut language = self.clone();
+
+        if let Some(sandbox) = &runref.sandbox {
+            match &mut language {
+                Language::JavaScript(javascript) => javascript.set_sandbox(sandbox.clone()),
+                Language::Wasm(wasm) => wasm.set_sandbox(sandbox.clone()),
+                _ => warn!("sandbox is only supported for javascript and wasm, ignoring it"),
+            }
+        }
+
+        if let (Language::Wasm(wasm), Some(environment)) = (&mut language, &runref.environment) {
+            wasm.set_environment(environment.keys().cloned().collect());
+        }
+
+        if let Some(options) = &runref.python {
+            match &mut language {
+                Language::Python(python) => python.set_options(options.clone()),
+                _ => warn!("python options set on a non python alias, ignoring them"),
+            }
+        }
+
+        if let Some(shell) = &runref.shell {
+            match &mut language {
+                Language::Shell(language) => language.set_shell(shell),
+                _ => warn!("shell set on a non shell alias, ignoring it"),
+            }
+        }
+
+        if let Some(options) = &runref.lua {
+            match &mut language {
+                Language::Lua(lua) => lua.set_options(options.clone()),
+                _ => warn!("lua options set on a non lua alias, ignoring them"),
+            }
+        }
+
+        language
+    }
+}
+
+impl Language {
+    /// The built in language handling files ending in `extension`.
+    fn from_extension(extension: &str) -> Option<Language> {
+        match extension {
+            "js" | "ts" => Some(Language::JavaScript(JavaScriptLanguage::new())),
+            "lua" => Some(Language::Lua(LuaLanguage::new())),
+            "py" => Some(Language::Python(PythonLanguage::new())),
+            "rhai" => Some(Language::Rhai(RhaiLanguage::new())),
+            "sh" | "shell" => Some(Language::Shell(ShellLanguage::from_shebang("sh"))),
+            "bash" | "zsh" | "fish" | "ksh" | "nu" => {
+                Some(Language::Shell(ShellLanguage::from_shebang(extension)))
+            }
+            "ps1" => Some(Language::Shell(ShellLanguage::from_shebang("pwsh"))),
+            "wasm" | "wat" => Some(Language::Wasm(WasmLanguage::new())),
+            _ => None,
         }
     }
+
+    /// The built in language called `name`.
+    fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "javascript" | "js" | "ts" => Some(Language::JavaScript(JavaScriptLanguage::new())),
+            "lua" => Some(Language::Lua(LuaLanguage::new())),
+            "python" | "py" => Some(Language::Python(PythonLanguage::new())),
+            "rhai" => Some(Language::Rhai(RhaiLanguage::new())),
+            "shell" => Some(Language::Shell(ShellLanguage::from_shebang("sh"))),
+            "wasm" => Some(Language::Wasm(WasmLanguage::new())),
+            shell if SHELLS.contains(&shell) => Some(Language::Shell(ShellLanguage::new(shell))),
+            _ => None,
+        }
+    }
+
+    /// Whether a user defined language would clash with a built in one, by
+    /// its name or one of its extensions.
+    pub fn is_builtin(name: &str, extensions: &[String]) -> bool {
+        Language::from_name(name).is_some()
+            || extensions
+                .iter()
+                .any(|ext| Language::from_extension(ext).is_some())
+    }
+
+    /// The language for `file` by its extension, built in languages first and
+    /// then the ones in `languages`.
+    pub fn from_file(
+        file: &str,
+        languages: &BTreeMap<String, LanguageCommand>,
+    ) -> Result<Language, anyhow::Error> {
+        let extension = file.split('.').last().unwrap();
+        Language::from_extension(extension)
+            .or_else(|| {
+                ExternalLanguage::from_extension(extension, languages).map(Language::External)
+            })
+            .ok_or(LanguageError::UnsupportedLanguage(extension.into()).into())
+    }
+
+    /// Find a user defined filetype in `languages`.
+    pub fn resolve(
+        &mut self,
+        languages: &BTreeMap<String, LanguageCommand>,
+    ) -> Result<(), LanguageError> {
+        if let Language::External(external) = self {
+            if !external.resolve(languages) {
+                let name = external.get_name().to_string();
+                return Err(LanguageError::UnsupportedLanguage(name));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<&str> for Language {
     type Error = anyhow::Error;
     fn try_from(file: &str) -> Result<Self, Self::Error> {
-        let extension = file.split('.').last().unwrap();
-        match extension {
-            "js" | "ts" => Ok(Language::JavaScript(JavaScriptLanguage::new())),
-            "lua" => Ok(Language::Lua(LuaLanguage::new())),
-            "py" => Ok(Language::Python(PythonLanguage::new())),
-            "sh" | "bash" | "zsh" | "shell" => Ok(Language::Shell(ShellLanguage::new(extension))),
-            language => Err(LanguageError::UnsupportedLanguage(language.into()).into()),
-        }
+        Language::from_file(file, &BTreeMap::new())
     }
 }
 
@@ -61,12 +190,7 @@ impl Serialize for Language {
     where
         S: serde::Serializer,
     {
-        match self {
-            Language::JavaScript(_) => serializer.serialize_str("javascript"),
-            Language::Lua(_) => serializer.serialize_str("lua"),
-            Language::Python(_) => serializer.serialize_str("python"),
-            Language::Shell(_) => serializer.serialize_str("shell"),
-        }
+        serializer.serialize_str(self.get_name())
     }
 }
 
@@ -76,17 +200,8 @@ impl<'a> Deserialize<'a> for Language {
         D: serde::Deserializer<'a>,
     {
         let value = String::deserialize(deserializer)?;
-        match value.as_str() {
-            "javascript" | "js" | "ts" => Ok(Language::JavaScript(JavaScriptLanguage::new())),
-            "lua" => Ok(Language::Lua(LuaLanguage::new())),
-            "python" | "py" => Ok(Language::Python(PythonLanguage::new())),
-            "shell" | "sh" | "bash" | "zsh" => {
-                Ok(Language::Shell(ShellLanguage::new(value.as_str())))
-            }
-            language => Err(serde::de::Error::custom(format!(
-                "Unsupported language: {language}",
-            ))),
-        }
+        Ok(Language::from_name(&value)
+            .unwrap_or_else(|| Language::External(ExternalLanguage::named(&value))))
     }
 }
 
@@ -99,7 +214,9 @@ pub enum LanguageError {
     #[cfg(any(
         not(feature = "python"), 
         not(feature = "javascript"), 
-        not(feature = "lua")
+        not(feature = "lua"),
+        not(feature = "rhai"),
+        not(feature = "wasm")
     ))]
     #[error("Feature not enabled for {0}")]
     FeatureNotEnabled(String),
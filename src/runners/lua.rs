@@ -1,32 +1,139 @@
 #![allow(unused_imports)]
 
-use std::fs;
+use std::{collections::HashMap, fs, path::PathBuf};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use log::debug;
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "lua")]
+use futures_util::future::try_join_all;
 #[cfg(feature = "lua")]
 use mlua::prelude::*;
 
-use super::{dev::Dev, language, language::LanguageFunctions, RunStatus};
+#[cfg(feature = "lua")]
+use super::log_output;
+use super::{dev::Dev, language, language::LanguageFunctions, RunStatus, HOST_API};
+use crate::utils::script_dir;
+
+// Wraps the functions that read the environment or start a shell so they
+// see the dev environment on top of the process one
+#[cfg(feature = "lua")]
+const SCOPED_ENV: &str = r#"
+local env, exports = ...
+local getenv, execute, popen = os.getenv, os.execute, io.popen
+
+os.getenv = function(key)
+  local value = env[key]
+  if value ~= nil then
+    return value
+  end
+  return getenv(key)
+end
+
+os.execute = function(command)
+  if command == nil then
+    return execute()
+  end
+  return execute(exports .. command)
+end
+
+io.popen = function(command, mode)
+  return popen(exports .. command, mode)
+end
+"#;
+
+/// Per-alias Lua settings, configured as `lua = { lib = "lua" }`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LuaOptions {
+    /// Extra directory searched by `require`, after the script's own
+    pub lib: Option<PathBuf>,
+}
 
 #[derive(Debug, Clone)]
-pub struct LuaLanguage {}
+pub struct LuaLanguage {
+    options: LuaOptions,
+}
 
 impl LuaLanguage {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            options: LuaOptions::default(),
+        }
+    }
+
+    pub fn set_options(&mut self, options: LuaOptions) {
+        self.options = options;
     }
 
+    #[cfg(feature = "lua")]
     fn init(&self, dev: &Dev) -> Result<Lua, anyhow::Error> {
-        // I guess we set with rust :(
-        for (key, value) in dev.get_env() {
-            std::env::set_var(key, value);
-        }
         let lua = Lua::new();
+
+        // The dev environment is only visible to this script, the process
+        // environment is shared by every alias running at the same time
+        let env = dev.get_env();
+        let exports = shell_exports(&env)?;
+        lua.load(SCOPED_ENV)
+            .set_name("=dev_env")
+            .call::<()>((lua.create_table_from(env)?, exports))?;
+
         Ok(lua)
     }
+
+    /// Make `require` find the `dev` module and Lua files next to the
+    /// script or in the configured lib directory.
+    #[cfg(feature = "lua")]
+    fn setup_require(
+        &self,
+        lua: &Lua,
+        dev: &LuaAnyUserData,
+        file: &str,
+    ) -> Result<(), anyhow::Error> {
+        let package: LuaTable = lua.globals().get("package")?;
+
+        let dev = dev.clone();
+        let load_dev = lua.create_function(move |_, ()| Ok(dev.clone()))?;
+        package.get::<LuaTable>("preload")?.set("dev", load_dev)?;
+
+        let mut dirs = vec![script_dir(file)?];
+        if let Some(lib) = &self.options.lib {
+            dirs.push(lib.clone());
+        }
+
+        let mut paths = dirs
+            .iter()
+            .flat_map(|dir| {
+                let dir = dir.display();
+                [format!("{dir}/?.lua"), format!("{dir}/?/init.lua")]
+            })
+            .collect::<Vec<String>>();
+        paths.push(package.get::<String>("path")?);
+        package.set("path", paths.join(";"))?;
+
+        Ok(())
+    }
+}
+
+/// `export` statements for `env`, prefixed to commands Lua hands to the shell.
+/// Values are quoted, names can't be so anything but a plain variable name is
+/// rejected.
+#[cfg_attr(not(feature = "lua"), allow(dead_code))]
+fn shell_exports(env: &HashMap<String, String>) -> Result<String, anyhow::Error> {
+    env.iter()
+        .map(|(key, value)| {
+            let mut chars = key.chars();
+            let valid = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(anyhow!("Invalid environment variable name: {key:?}"));
+            }
+            Ok(format!("export {key}='{}'; ", value.replace('\'', "'\\''")))
+        })
+        .collect()
 }
 
 impl Default for LuaLanguage {
@@ -81,35 +188,37 @@ impl LuaLanguage {
         let lua = self.init(&dev)?;
         let globals = lua.globals();
 
-        // let load = lua.create_function(move |lua, modname: String| {
-        //     let rectangle = Rectangle {
-        //         name: "Rectangle".to_string(),
-        //         length: 0,
-        //         width: 0,
-        //     };
-        //     let m = lua.create_table()?;
-        //     m.set("__name", modname)?;
-        //     m.set("rec", rectangle)?;
-        //     m.set("v", "1.0")?;
-        //     Ok(m)
-        // })?;
-        // let t: mlua::Table = lua.load_from_function("test", load.clone())?;
-
-        // globals.set("test", t)?;
-        globals.set("dev", lua.create_ser_userdata(dev)?)?;
+        // `require 'dev'` hands back the same userdata as the global
+        let dev = lua.create_ser_userdata(dev)?;
+        self.setup_require(&lua, &dev, file)?;
+        globals.set("dev", dev)?;
 
         let lua_code = fs::read_to_string(file)?;
-        let m: mlua::Table = lua.load(&lua_code).eval()?;
+        let chunk = lua.load(&lua_code).set_name(format!("@{file}"));
 
-        let dev: Dev = lua.from_value(m.get("Out")?)?;
-        debug!("{:?}", dev);
-
-        let init: String = m.get::<mlua::Function>("init")?.call(())?;
-        debug!("{}", init);
+        // Plain scripts do their work at the top level and return nothing or
+        // a value, module style scripts return a table with an optional `Out`
+        // and `init`, the output is `Out` or else whatever `init` returns
+        let output = match chunk.eval_async::<LuaValue>().await? {
+            LuaValue::Table(m) => {
+                let out = m.get::<LuaValue>("Out")?;
+                let init = match m.get::<Option<LuaFunction>>("init")? {
+                    Some(init) => init.call_async::<LuaValue>(()).await?,
+                    None => LuaValue::Nil,
+                };
+                if out.is_nil() {
+                    init
+                } else {
+                    out
+                }
+            }
+            value => value,
+        };
 
         Ok(RunStatus {
             exit_code: Some(0),
             message: Some("success".to_string()),
+            output: to_output(&lua, output)?,
         })
     }
 
@@ -126,15 +235,107 @@ impl LuaLanguage {
     }
 }
 
+/// A script's result as JSON, functions and other values that have no JSON
+/// form are left out.
+#[cfg(feature = "lua")]
+fn to_output(lua: &Lua, value: LuaValue) -> LuaResult<Option<serde_json::Value>> {
+    if value.is_nil() {
+        return Ok(None);
+    }
+
+    let options = LuaDeserializeOptions::new().deny_unsupported_types(false);
+    let output = lua.from_value_with::<serde_json::Value>(value, options)?;
+    log_output("lua", &output);
+    Ok(Some(output))
+}
+
+/// Host function arguments as JSON, Lua can't tell an empty list from an
+/// empty table so those are taken as lists.
+#[cfg(feature = "lua")]
+fn to_json(lua: &Lua, args: LuaMultiValue) -> LuaResult<Vec<serde_json::Value>> {
+    args.into_iter()
+        .map(|arg| match lua.from_value::<serde_json::Value>(arg)? {
+            serde_json::Value::Object(map) if map.is_empty() => {
+                Ok(serde_json::Value::Array(vec![]))
+            }
+            value => Ok(value),
+        })
+        .collect()
+}
+
+/// Host function results as Lua values, with null as nil rather than the
+/// `null` sentinel so results can be tested with `== nil`.
+#[cfg(feature = "lua")]
+fn from_json(lua: &Lua, value: &serde_json::Value) -> LuaResult<LuaValue> {
+    let options = LuaSerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+    lua.to_value_with(value, options)
+}
+
 #[cfg(feature = "lua")]
 impl LuaUserData for Dev {
     fn add_methods<'lua, M: LuaUserDataMethods<Self>>(methods: &mut M) {
-        methods.add_method("get_version", |_, this, ()| Ok(this.get_version()));
-        methods.add_method("get_dir", |_, this, ()| Ok(this.get_dir()));
+        for function in HOST_API {
+            if function.blocking {
+                // Async methods yield back to the runtime while they wait, so
+                // `dev:all` can run several of them side by side
+                methods.add_async_method(function.name, move |lua, this, args: LuaMultiValue| {
+                    let mut dev = Dev::clone(&this);
+                    let args = to_json(&lua, args);
+                    async move {
+                        let args = args?;
+                        let value = tokio::task::spawn_blocking(move || {
+                            function.call("lua", &mut dev, args)
+                        })
+                        .await
+                        .map_err(LuaError::external)?
+                        .map_err(|e| LuaError::external(e.to_string()))?;
+                        from_json(&lua, &value)
+                    }
+                });
+            } else {
+                methods.add_method_mut(function.name, move |lua, this, args: LuaMultiValue| {
+                    let value = function
+                        .call("lua", this, to_json(lua, args)?)
+                        .map_err(|e| LuaError::external(e.to_string()))?;
+                    from_json(lua, &value)
+                });
+            }
+        }
+
+        #[cfg(feature = "github")]
+        methods.add_async_method(
+            "open_pr",
+            |lua, _this, (base, head, title, repo_id): (String, String, String, String)| async move {
+                let pr = crate::github::client::open_pr(&base, &head, &title, &repo_id)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+
+                let result = lua.create_table()?;
+                result.set("number", pr.number)?;
+                result.set("title", pr.title)?;
+                result.set("base", pr.base_ref_name)?;
+                result.set("head", pr.head_ref_name)?;
+                Ok(result)
+            },
+        );
+
+        // dev:all { f, g, ... } runs each function as its own coroutine and
+        // returns their results once every one of them has finished
+        methods.add_async_method("all", |lua, _this, tasks: Vec<LuaFunction>| async move {
+            let threads = tasks
+                .into_iter()
+                .map(|task| lua.create_thread(task)?.into_async::<LuaValue>(()))
+                .collect::<LuaResult<Vec<_>>>()?;
+
+            try_join_all(threads).await
+        });
 
         methods.add_meta_method(LuaMetaMethod::Index, |lua, this, key: String| {
             match key.as_str() {
-                "version" => Ok(lua.create_string(&this.version)?),
+                "version" => Ok(LuaValue::String(lua.create_string(&this.version)?)),
+                "deps" => from_json(lua, &serde_json::Value::Object(this.deps.clone())),
                 _ => Err(mlua::Error::RuntimeError("Attribute not found".to_string())),
             }
         });
@@ -187,3 +388,73 @@ impl LuaUserData for Dev {
 //         methods.add_meta_function(mlua::MetaMethod::Call, |_, ()| Ok(Rectangle::default()));
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_exports_quotes_values() {
+        let env = HashMap::from([("GREETING".to_string(), "it's $HOME".to_string())]);
+        assert_eq!(
+            shell_exports(&env).unwrap(),
+            "export GREETING='it'\\''s $HOME'; "
+        );
+    }
+
+    #[test]
+    fn shell_exports_rejects_names() {
+        for key in ["A;rm -rf ~", "TWO WORDS", "$(id)", "1ST", ""] {
+            let env = HashMap::from([(key.to_string(), "value".to_string())]);
+            assert!(shell_exports(&env).is_err(), "{key:?} was exported");
+        }
+
+        let env = HashMap::from([("_PRIVATE_2".to_string(), "value".to_string())]);
+        assert!(shell_exports(&env).is_ok());
+    }
+
+    #[cfg(feature = "lua")]
+    async fn output(source: &str) -> Option<serde_json::Value> {
+        use std::io::Write;
+
+        let mut file = tempfile::Builder::new().suffix(".lua").tempfile().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+
+        let dev = Dev::new(&crate::config::Config::default());
+        let path = file.path().to_string_lossy().into_owned();
+        let status = LuaLanguage::new().run_file(dev, &path, vec![]).await;
+        status.unwrap().output
+    }
+
+    #[cfg(feature = "lua")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn output_shapes() {
+        use serde_json::json;
+
+        assert_eq!(output("local x = 1").await, None);
+        assert_eq!(output("return nil").await, None);
+        assert_eq!(output("return { init = function() end }").await, None);
+
+        assert_eq!(
+            output(r#"return { name = "dev", count = 2, ratio = 0.5, ok = true }"#).await,
+            Some(json!({ "name": "dev", "count": 2, "ratio": 0.5, "ok": true }))
+        );
+        assert_eq!(
+            output(r#"return { "a", "b", { 1, 2 } }"#).await,
+            Some(json!(["a", "b", [1, 2]]))
+        );
+        assert_eq!(
+            output("return { Out = { 1 }, init = function() return 2 end }").await,
+            Some(json!([1]))
+        );
+        assert_eq!(
+            output("return { init = function() return 2 end }").await,
+            Some(json!(2))
+        );
+
+        let dev = output(r#"return { init = function() dev.version = "2.0.0"; return dev end }"#)
+            .await
+            .unwrap();
+        assert_eq!(dev["version"], "2.0.0");
+    }
+}
@@ -1,17 +1,37 @@
 mod language;
+mod deps;
 mod dev;
+mod external;
+mod host;
 mod javascript;
 mod lua;
 mod python;
+mod rhai;
+mod sandbox;
 mod shell;
+mod wasm;
 
 pub use dev::Dev;
+pub use external::LanguageCommand;
+pub use host::{HostFunction, HOST_API};
 pub use language::{Language, LanguageFunctions};
+pub use lua::LuaOptions;
+pub use python::PythonOptions;
+pub use sandbox::{Capability, Sandbox};
+
+use anyhow::anyhow;
+use log::debug;
+use serde_json::json;
+
+use crate::{config::Config, utils::write_tmp_file};
 
 #[derive(Debug)]
 pub struct RunStatus {
     pub exit_code: Option<i32>,
     pub message: Option<String>,
+    /// What the script handed back: Python's `build`, Lua's `Out`, the
+    /// JavaScript default export or the last Rhai expression
+    pub output: Option<serde_json::Value>,
 }
 
 impl std::fmt::Display for RunStatus {
@@ -34,6 +54,7 @@ pub struct RunError {
 impl std::fmt::Display for RunError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match (&self.exit_code, &self.message) {
+            (Some(code), Some(msg)) => write!(f, "({code}) {msg}"),
             (Some(code), None) => write!(f, "({})RunStatus: None", code),
             (None, Some(msg)) => write!(f, "RunStatus: {}", msg),
             _ => write!(f, "RunError: None"),
@@ -42,3 +63,104 @@ impl std::fmt::Display for RunError {
 }
 
 impl std::error::Error for RunError {}
+
+/// Run the alias `alias` from `config` with its configured language, after
+/// the aliases it depends on.
+pub async fn run_alias(
+    config: &Config,
+    alias: &str,
+    args: Option<Vec<&str>>,
+) -> Result<RunStatus, anyhow::Error> {
+    let mut args = args;
+    let mut results = serde_json::Map::new();
+    let mut status = None;
+
+    for name in deps::run_order(config, alias)? {
+        let args = if name == alias { args.take() } else { None };
+        let run_status = run_one(config, &name, args, &results).await?;
+
+        results.insert(
+            name,
+            json!({
+                "exit_code": run_status.exit_code,
+                "output": run_status.output,
+            }),
+        );
+        status = Some(run_status);
+    }
+
+    status.ok_or(anyhow!("Command '{alias}' not found in config"))
+}
+
+/// Run a single alias, `results` holds what the aliases that already ran
+/// handed back.
+async fn run_one(
+    config: &Config,
+    alias: &str,
+    args: Option<Vec<&str>>,
+    results: &serde_json::Map<String, serde_json::Value>,
+) -> Result<RunStatus, anyhow::Error> {
+    let args = args.unwrap_or_default();
+
+    let runref = config
+        .get_run(alias)
+        .ok_or(anyhow!("Command '{alias}' not found in config"))?;
+
+    let lang = runref
+        .filetype
+        .as_ref()
+        .ok_or(anyhow!("runner ref filetype not found"))?
+        .configure(runref);
+
+    // An alias only sees the results of its own dependencies
+    let deps = runref
+        .dependencies
+        .iter()
+        .flatten()
+        .filter_map(|name| Some((name.clone(), results.get(name)?.clone())))
+        .collect::<serde_json::Map<_, _>>();
+
+    let mut dev = Dev::new(config);
+    if let Some(environment) = &runref.environment {
+        for (key, value) in environment {
+            dev.add_env((key.clone(), deps::render(value, &deps)?));
+        }
+    }
+    dev.deps = deps.clone();
+
+    let mut status = RunStatus {
+        exit_code: Some(0),
+        message: None,
+        output: None,
+    };
+
+    let file = runref.file.as_ref();
+    let command = runref.command.as_ref();
+    if let Some(f) = file {
+        status = lang.run_file(dev.clone(), f, vec![]).await?;
+        debug!("status: {}", status);
+    }
+
+    if let Some(c) = command {
+        let c = deps::render(c, &deps)?;
+        let tmpfilepath = format!("{}{}", config.get_tmp_dir(), lang.get_extension());
+        write_tmp_file(tmpfilepath.as_str(), &c, true)?;
+        status = lang.run_file(dev, tmpfilepath.as_str(), args).await?;
+        debug!("status: {}", status);
+    }
+    Ok(status)
+}
+
+/// Log what a script handed back, as a `Dev` when it has that shape.
+#[cfg(any(
+    feature = "javascript",
+    feature = "python",
+    feature = "lua",
+    feature = "rhai"
+))]
+fn log_output(target: &str, output: &serde_json::Value) {
+    match serde_json::from_value::<Dev>(output.clone()) {
+        Ok(dev) => debug!(target: target, "{:?}", dev),
+        Err(_) => debug!(target: target, "{}", output),
+    }
+}
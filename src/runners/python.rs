@@ -1,38 +1,123 @@
 #![allow(unused_imports)]
 
-use std::{fs, path::Path, process::Command};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use pyo3::types::IntoPyDict;
 
-use super::{dev::Dev, language, language::LanguageFunctions, RunStatus};
+#[cfg(feature = "python")]
+use super::log_output;
+use super::{dev::Dev, language, language::LanguageFunctions, RunError, RunStatus};
+use crate::utils::script_dir;
+
+#[cfg(feature = "python")]
+static PYTHON_INIT: std::sync::Once = std::sync::Once::new();
+
+// The `dev` module functions are plain statics, this is the run they
+// belong to
+#[cfg(feature = "python")]
+static CURRENT_DEV: Mutex<Option<Dev>> = Mutex::new(None);
+
+/// Per-alias interpreter settings, configured as
+/// `python = { venv = ".venv", interpreter = "python3" }`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PythonOptions {
+    /// Virtualenv to run in, when unset a `.venv` next to the script or
+    /// in the working directory is picked up automatically
+    pub venv: Option<PathBuf>,
+    /// Run the script with this interpreter as a subprocess instead of
+    /// the embedded one
+    pub interpreter: Option<String>,
+}
 
 #[derive(Debug, Clone)]
-pub struct PythonLanguage {}
+pub struct PythonLanguage {
+    options: PythonOptions,
+}
 
 impl PythonLanguage {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            options: PythonOptions::default(),
+        }
+    }
+
+    pub fn set_options(&mut self, options: PythonOptions) {
+        self.options = options;
+    }
+
+    fn find_venv(&self, script_dir: &Path) -> Result<Option<PathBuf>, anyhow::Error> {
+        let is_venv = |dir: &Path| dir.join("pyvenv.cfg").is_file();
+
+        match &self.options.venv {
+            Some(venv) if is_venv(venv) => Ok(Some(venv.canonicalize()?)),
+            Some(venv) => Err(anyhow!("{} is not a virtualenv", venv.display())),
+            None => [script_dir.join(".venv"), PathBuf::from(".venv")]
+                .into_iter()
+                .find(|venv| is_venv(venv))
+                .map(|venv| venv.canonicalize())
+                .transpose()
+                .map_err(Into::into),
+        }
+    }
+
+    async fn run_interpreter(
+        &self,
+        interpreter: &str,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        let mut command = tokio::process::Command::new(interpreter);
+        command.arg(file).args(&args).envs(dev.get_env());
+
+        if let Some(venv) = self.find_venv(&script_dir(file)?)? {
+            debug!(target: "python", "using virtualenv {}", venv.display());
+            command
+                .env("VIRTUAL_ENV", &venv)
+                .env("PATH", venv_path(&venv)?);
+        }
+
+        let status = command.status().await.map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => anyhow!("Python interpreter '{interpreter}' not found"),
+            _ => e.into(),
+        })?;
+
+        match status.code() {
+            Some(0) => Ok(RunStatus {
+                exit_code: Some(0),
+                message: None,
+                output: None,
+            }),
+            code => Err(anyhow!(RunError {
+                exit_code: code,
+                message: Some(format!("Failed to run file: {file}, got {code:?}")),
+            })),
+        }
     }
 
     #[cfg(feature = "python")]
     fn init(&self, dev: &Dev) -> Result<(), anyhow::Error> {
-        pyo3::append_to_inittab!(dev);
-        pyo3::prepare_freethreaded_python();
-
-        Python::with_gil(|py| {
-            let os = py.import_bound("os")?;
-            let environ = os.getattr("environ")?;
-            let env_vars = dev.get_env().into_py_dict_bound(py);
-            environ.call_method1("update", (env_vars,))?;
+        // The inittab can only be extended before the interpreter starts
+        PYTHON_INIT.call_once(|| {
+            pyo3::append_to_inittab!(dev);
+            pyo3::prepare_freethreaded_python();
+        });
 
-            Ok(())
-        })
+        *CURRENT_DEV.lock().unwrap() = Some(dev.clone());
+        Ok(())
     }
 }
 
@@ -42,6 +127,13 @@ impl Default for PythonLanguage {
     }
 }
 
+/// `PATH` with the virtualenv's bin directory in front, like `activate` does.
+fn venv_path(venv: &Path) -> Result<std::ffi::OsString, anyhow::Error> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    let paths = std::iter::once(venv.join("bin")).chain(env::split_paths(&path));
+    Ok(env::join_paths(paths)?)
+}
+
 #[async_trait]
 impl language::LanguageFunctions for PythonLanguage {
     #[allow(unused_variables)]
@@ -51,6 +143,10 @@ impl language::LanguageFunctions for PythonLanguage {
         file: &str,
         args: Vec<&str>,
     ) -> Result<RunStatus, anyhow::Error> {
+        if let Some(interpreter) = &self.options.interpreter {
+            return self.run_interpreter(interpreter, dev, file, args).await;
+        }
+
         #[cfg(not(feature = "python"))]
         return Err(anyhow!("python support is not enabled"));
 
@@ -83,23 +179,28 @@ impl PythonLanguage {
         &self,
         dev: Dev,
         file: &str,
-        _args: Vec<&str>,
+        args: Vec<&str>,
     ) -> Result<RunStatus, anyhow::Error> {
         self.init(&dev)?;
 
-        Python::with_gil(|py| {
-            let file_contents = fs::read_to_string(Path::new(file))?;
-            let dev_out: Dev =
-                PyModule::from_code_bound(py, &file_contents, "version", "version_info")?
-                    .getattr("build")?
-                    .extract()?;
-
-            debug!(target: "python", "{:?}", dev_out);
+        let file_contents = fs::read_to_string(Path::new(file))?;
+        let script_dir = script_dir(file)?;
+        let venv = self.find_venv(&script_dir)?;
+        let env = dev.get_env();
 
-            Ok(RunStatus {
-                exit_code: Some(0),
-                message: Some("success".to_string()),
-            })
+        Python::with_gil(|py| {
+            let saved = SavedState::save(py, env.keys())?;
+            let status = run_main(
+                py,
+                file,
+                &file_contents,
+                &script_dir,
+                venv.as_deref(),
+                &args,
+                &env,
+            );
+            saved.restore(py)?;
+            status
         })
     }
 
@@ -116,25 +217,423 @@ impl PythonLanguage {
     }
 }
 
+/// Run `file` as `__main__`, mirroring what `python file args...` sets up
+/// before running a script.
+#[cfg(feature = "python")]
+fn run_main(
+    py: Python,
+    file: &str,
+    file_contents: &str,
+    script_dir: &Path,
+    venv: Option<&Path>,
+    args: &[&str],
+    env: &HashMap<String, String>,
+) -> Result<RunStatus, anyhow::Error> {
+    let environ = py.import_bound("os")?.getattr("environ")?;
+    environ.call_method1("update", (env.clone().into_py_dict_bound(py),))?;
+
+    let sys = py.import_bound("sys")?;
+    let argv = std::iter::once(file)
+        .chain(args.iter().copied())
+        .collect::<Vec<&str>>();
+    sys.setattr("argv", argv)?;
+    sys.getattr("path")?
+        .call_method1("insert", (0, script_dir))?;
+
+    if let Some(venv) = venv {
+        activate_venv(py, venv)?;
+    }
+
+    let builtins = py.import_bound("builtins")?;
+    let main = PyModule::new_bound(py, "__main__")?;
+    main.setattr("__file__", file)?;
+    main.setattr("__builtins__", &builtins)?;
+    sys.getattr("modules")?.set_item("__main__", &main)?;
+
+    let result = (|| -> PyResult<Option<String>> {
+        // Compiling with the real file name keeps it in tracebacks
+        let code = builtins
+            .getattr("compile")?
+            .call1((file_contents, file, "exec"))?;
+        builtins.getattr("exec")?.call1((code, main.dict()))?;
+
+        if !main.hasattr("build")? {
+            return Ok(None);
+        }
+
+        // Paths and the like can't be encoded, they're handed back as strings
+        let json = py.import_bound("json")?;
+        let kwargs = [("default", builtins.getattr("str")?)].into_py_dict_bound(py);
+        let build = json.call_method("dumps", (main.getattr("build")?,), Some(&kwargs))?;
+        Ok(Some(build.extract()?))
+    })();
+
+    match result {
+        Ok(build) => {
+            let output = build
+                .map(|build| serde_json::from_str(&build))
+                .transpose()?;
+            if let Some(output) = &output {
+                log_output("python", output);
+            }
+
+            Ok(RunStatus {
+                exit_code: Some(0),
+                message: Some("success".to_string()),
+                output,
+            })
+        }
+        Err(e) => exit_status(py, e),
+    }
+}
+
+/// What a run changes in the interpreter every script shares, put back once
+/// it's done so nothing carries over into the next run or the script that
+/// started it.
+#[cfg(feature = "python")]
+struct SavedState {
+    // A nested run replaces both for the script that called dev.run, the
+    // first run finds neither
+    argv: Option<PyObject>,
+    main: Option<PyObject>,
+    path: PyObject,
+    prefix: PyObject,
+    exec_prefix: PyObject,
+    // The alias environment and what activating a virtualenv sets, None
+    // for the variables that weren't set before
+    environ: Vec<(String, Option<String>)>,
+}
+
+#[cfg(feature = "python")]
+impl SavedState {
+    /// Save the interpreter state and the variables in `env`, which the run
+    /// is about to set.
+    fn save<'a>(py: Python, env: impl Iterator<Item = &'a String>) -> PyResult<Self> {
+        let sys = py.import_bound("sys")?;
+        let environ = py.import_bound("os")?.getattr("environ")?;
+        let environ = ["VIRTUAL_ENV", "PATH"]
+            .into_iter()
+            .map(str::to_string)
+            .chain(env.cloned())
+            .map(|key| {
+                let value = environ.call_method1("get", (&key,))?.extract()?;
+                Ok((key, value))
+            })
+            .collect::<PyResult<_>>()?;
+
+        let main = sys.getattr("modules")?.call_method1("get", ("__main__",))?;
+
+        Ok(Self {
+            argv: sys.getattr("argv").ok().map(Bound::unbind),
+            main: (!main.is_none()).then(|| main.unbind()),
+            path: sys.getattr("path")?.call_method0("copy")?.unbind(),
+            prefix: sys.getattr("prefix")?.unbind(),
+            exec_prefix: sys.getattr("exec_prefix")?.unbind(),
+            environ,
+        })
+    }
+
+    fn restore(self, py: Python) -> PyResult<()> {
+        let sys = py.import_bound("sys")?;
+        match self.argv {
+            Some(argv) => sys.setattr("argv", argv)?,
+            None if sys.hasattr("argv")? => sys.delattr("argv")?,
+            None => {}
+        }
+        let modules = sys.getattr("modules")?;
+        match self.main {
+            Some(main) => modules.set_item("__main__", main)?,
+            None => {
+                modules.call_method1("pop", ("__main__", py.None()))?;
+            }
+        }
+        sys.setattr("path", self.path)?;
+        sys.setattr("prefix", self.prefix)?;
+        sys.setattr("exec_prefix", self.exec_prefix)?;
+
+        let environ = py.import_bound("os")?.getattr("environ")?;
+        for (key, value) in self.environ {
+            match value {
+                Some(value) => environ.set_item(key, value)?,
+                None => {
+                    environ.call_method1("pop", (key, py.None()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turn an exception escaping the script into what `python file` would have
+/// done with it: `SystemExit` sets the exit code, anything else exits with 1
+/// and the formatted traceback.
+#[cfg(feature = "python")]
+fn exit_status(py: Python, e: PyErr) -> Result<RunStatus, anyhow::Error> {
+    if e.is_instance_of::<pyo3::exceptions::PySystemExit>(py) {
+        let code = e.value_bound(py).getattr("code")?;
+        let (exit_code, message) = if code.is_none() {
+            (0, None)
+        } else if let Ok(exit_code) = code.extract::<i32>() {
+            (exit_code, None)
+        } else {
+            // sys.exit("message") prints the message and exits with 1
+            (1, Some(code.str()?.to_string()))
+        };
+
+        return match exit_code {
+            0 => Ok(RunStatus {
+                exit_code: Some(0),
+                message: None,
+                output: None,
+            }),
+            _ => Err(anyhow!(RunError {
+                exit_code: Some(exit_code),
+                message: message.or(Some(format!("Script exited with code {exit_code}"))),
+            })),
+        };
+    }
+
+    let traceback = py
+        .import_bound("traceback")?
+        .getattr("format_exception")?
+        .call1((
+            e.get_type_bound(py),
+            e.value_bound(py),
+            e.traceback_bound(py),
+        ))?
+        .extract::<Vec<String>>()?
+        .concat();
+
+    Err(anyhow!(RunError {
+        exit_code: Some(1),
+        message: Some(traceback.trim_end().to_string()),
+    }))
+}
+
+/// Point the embedded interpreter at a virtualenv the way `activate` and
+/// `site` would for the venv's own python binary, for as long as the run
+/// lasts, `SavedState` puts everything back.
+#[cfg(feature = "python")]
+fn activate_venv(py: Python, venv: &Path) -> Result<(), anyhow::Error> {
+    let version = py.version_info();
+    let site_packages = venv
+        .join("lib")
+        .join(format!("python{}.{}", version.major, version.minor))
+        .join("site-packages");
+
+    if !site_packages.is_dir() {
+        return Err(anyhow!(
+            "{} has no site-packages for python{}.{}, the version dev was built against",
+            venv.display(),
+            version.major,
+            version.minor,
+        ));
+    }
+
+    debug!(target: "python", "using virtualenv {}", venv.display());
+
+    let sys = py.import_bound("sys")?;
+    sys.setattr("prefix", venv)?;
+    sys.setattr("exec_prefix", venv)?;
+
+    // addsitedir appends, put the venv right after the script directory so
+    // its packages win over the system ones and let it process any .pth files
+    sys.getattr("path")?
+        .call_method1("insert", (1, &site_packages))?;
+    py.import_bound("site")?
+        .call_method1("addsitedir", (&site_packages,))?;
+
+    let environ = py.import_bound("os")?.getattr("environ")?;
+    environ.set_item("VIRTUAL_ENV", venv)?;
+    environ.set_item("PATH", venv_path(venv)?)?;
+
+    Ok(())
+}
+
+/// The `dev` module, one callable per host function taking the arguments
+/// positionally or by name.
 #[cfg(feature = "python")]
 #[pymodule]
 mod dev {
     use super::*;
+    use pyo3::{
+        exceptions::{PyAttributeError, PyRuntimeError, PyTypeError},
+        types::{PyDict, PyTuple},
+    };
 
-    #[pyfunction]
-    fn get_version() -> PyResult<String> {
-        Ok("0.1.0".to_string())
+    use crate::runners::{HostFunction, HOST_API};
+
+    #[pymodule_init]
+    fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+        for function in HOST_API {
+            m.add(function.name, Function { function })?;
+        }
+        Ok(())
     }
 
+    /// `dev.deps`, looked up on each access so nested runs see their own.
     #[pyfunction]
-    fn get_env() -> PyResult<String> {
-        let env = String::from_utf8_lossy(&Command::new("env").output()?.stdout).to_string();
-        Ok(env)
+    fn __getattr__(py: Python, name: &str) -> PyResult<PyObject> {
+        if name != "deps" {
+            return Err(PyAttributeError::new_err(format!(
+                "module 'dev' has no attribute '{name}'"
+            )));
+        }
+
+        let deps = CURRENT_DEV
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|dev| dev.deps.clone())
+            .unwrap_or_default();
+        from_json(py, &serde_json::Value::Object(deps))
     }
 
-    #[pyfunction]
-    fn get_work_dir() -> PyResult<String> {
-        let pwd = String::from_utf8_lossy(&Command::new("pwd").output()?.stdout).to_string();
-        Ok(pwd)
+    #[pyclass(frozen, module = "dev")]
+    struct Function {
+        function: &'static HostFunction,
+    }
+
+    #[pymethods]
+    impl Function {
+        #[pyo3(signature = (*args, **kwargs))]
+        fn __call__(
+            &self,
+            py: Python,
+            args: &Bound<'_, PyTuple>,
+            kwargs: Option<&Bound<'_, PyDict>>,
+        ) -> PyResult<PyObject> {
+            let name = self.function.name;
+            let mut args = args.iter().collect::<Vec<_>>();
+
+            if let Some(kwargs) = kwargs {
+                for (key, value) in kwargs.iter() {
+                    let key = key.extract::<String>()?;
+                    let index = self
+                        .function
+                        .param_names()
+                        .position(|param| param == key)
+                        .ok_or_else(|| {
+                            PyTypeError::new_err(format!(
+                                "{name}() got an unexpected argument '{key}'"
+                            ))
+                        })?;
+
+                    if index < args.len() && !args[index].is_none() {
+                        return Err(PyTypeError::new_err(format!(
+                            "{name}() got multiple values for argument '{key}'"
+                        )));
+                    }
+                    if index >= args.len() {
+                        args.resize(index + 1, py.None().into_bound(py));
+                    }
+                    args[index] = value;
+                }
+            }
+
+            let json = py.import_bound("json")?;
+            let args = args
+                .into_iter()
+                .map(|arg| to_json(&json, &arg))
+                .collect::<PyResult<Vec<serde_json::Value>>>()?;
+
+            let mut dev = CURRENT_DEV
+                .lock()
+                .unwrap()
+                .clone()
+                .ok_or_else(|| PyRuntimeError::new_err("dev is not running a script"))?;
+
+            // Blocking functions may well run another Python script, let go of
+            // the GIL and put this run back once they're done
+            let result = if self.function.blocking {
+                py.allow_threads(|| self.function.call("python", &mut dev, args))
+            } else {
+                self.function.call("python", &mut dev, args)
+            };
+            *CURRENT_DEV.lock().unwrap() = Some(dev);
+
+            let value = result.map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+            from_json(py, &value)
+        }
+
+        fn __repr__(&self) -> String {
+            format!("<dev.{}>", self.function.name)
+        }
+    }
+
+    fn from_json(py: Python, value: &serde_json::Value) -> PyResult<PyObject> {
+        let value =
+            serde_json::to_string(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(py
+            .import_bound("json")?
+            .call_method1("loads", (value,))?
+            .unbind())
+    }
+
+    /// Paths and other objects json can't encode go across as strings.
+    fn to_json(
+        json: &Bound<'_, PyModule>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<serde_json::Value> {
+        let py = json.py();
+        let kwargs =
+            [("default", py.get_type_bound::<pyo3::types::PyString>())].into_py_dict_bound(py);
+        let value = json
+            .call_method("dumps", (value,), Some(&kwargs))?
+            .extract::<String>()?;
+        serde_json::from_str(&value).map_err(|e| PyTypeError::new_err(e.to_string()))
+    }
+}
+
+#[cfg(all(test, feature = "python"))]
+mod tests {
+    use std::io::Write;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Config;
+
+    async fn run(source: &str, env: &[(&str, &str)]) -> Result<RunStatus, anyhow::Error> {
+        let mut file = tempfile::Builder::new().suffix(".py").tempfile()?;
+        file.write_all(source.as_bytes())?;
+
+        let mut dev = Dev::new(&Config::default());
+        for (key, value) in env {
+            dev.add_env((key.to_string(), value.to_string()));
+        }
+        let path = file.path().to_string_lossy().into_owned();
+        PythonLanguage::new().run_file(dev, &path, vec![]).await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn alias_env_is_scoped_to_the_run() {
+        let source = "import os\nbuild = os.environ.get('DEV_PYTHON_TEST_ENV')\n";
+
+        let status = run(source, &[("DEV_PYTHON_TEST_ENV", "first")])
+            .await
+            .unwrap();
+        assert_eq!(status.output, Some(json!("first")));
+
+        let status = run(source, &[]).await.unwrap();
+        assert_eq!(status.output, Some(json!(null)));
+        assert!(env::var("DEV_PYTHON_TEST_ENV").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn system_exit_codes() {
+        let error = run("raise SystemExit(3)", &[]).await.unwrap_err();
+        let error = error.downcast_ref::<RunError>().unwrap();
+        assert_eq!(error.exit_code, Some(3));
+
+        let status = run("import sys\nsys.exit()", &[]).await.unwrap();
+        assert_eq!(status.exit_code, Some(0));
+
+        let error = run("import sys\nsys.exit('broken')", &[])
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<RunError>().unwrap();
+        assert_eq!(error.exit_code, Some(1));
+        assert_eq!(error.message.as_deref(), Some("broken"));
     }
 }
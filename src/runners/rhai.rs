@@ -0,0 +1,182 @@
+#![allow(unused_imports)]
+
+use std::{any::TypeId, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::debug;
+
+#[cfg(feature = "rhai")]
+use ::rhai::{
+    module_resolvers::FileModuleResolver,
+    serde::{from_dynamic, to_dynamic},
+    Array, Dynamic, Engine, EvalAltResult, Scope,
+};
+
+#[cfg(feature = "rhai")]
+use super::log_output;
+use super::{dev::Dev, language, HostFunction, RunError, RunStatus, HOST_API};
+use crate::utils::script_dir;
+
+/// Embedded Rhai scripts, pure Rust so it's available in minimal builds
+/// without Python or V8.
+#[derive(Debug, Clone)]
+pub struct RhaiLanguage {}
+
+impl RhaiLanguage {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for RhaiLanguage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl language::LanguageFunctions for RhaiLanguage {
+    #[allow(unused_variables)]
+    async fn run_file(
+        &self,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        #[cfg(not(feature = "rhai"))]
+        return Err(anyhow!("rhai support is not enabled"));
+
+        #[cfg(feature = "rhai")]
+        return self.run_file(dev, file, args).await;
+    }
+
+    #[allow(unused_variables)]
+    async fn load_file(&self, file: &str) -> Result<(), anyhow::Error> {
+        #[cfg(not(feature = "rhai"))]
+        return Err(anyhow!("rhai support is not enabled"));
+
+        #[cfg(feature = "rhai")]
+        return self.load_file(file).await;
+    }
+
+    async fn run_shell(
+        &self,
+        _command: &str,
+        _args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        Err(anyhow!("rhai has no interactive shell"))
+    }
+}
+
+#[cfg(feature = "rhai")]
+impl RhaiLanguage {
+    async fn run_file(
+        &self,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        let file = file.to_string();
+        let args = args
+            .iter()
+            .map(|arg| Dynamic::from(arg.to_string()))
+            .collect();
+
+        // Scripts run synchronously, keep them off the runtime's workers
+        tokio::task::spawn_blocking(move || run_script(dev, &file, args)).await?
+    }
+
+    async fn load_file(&self, file: &str) -> Result<(), anyhow::Error> {
+        engine(script_dir(file)?)
+            .compile_file(file.into())
+            .map_err(|e| anyhow!("{file}: {e}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rhai")]
+fn run_script(dev: Dev, file: &str, args: Array) -> Result<RunStatus, anyhow::Error> {
+    let engine = engine(script_dir(file)?);
+
+    let mut scope = Scope::new();
+    scope.push("dev", dev);
+    scope.push_constant("ARGS", args);
+
+    let result = engine
+        .eval_file_with_scope::<Dynamic>(&mut scope, file.into())
+        .map_err(|e| {
+            anyhow!(RunError {
+                exit_code: Some(1),
+                message: Some(format!("{file}: {e}")),
+            })
+        })?;
+
+    // Like Lua, a script can end with its build state or any other value
+    let output = if result.is_unit() {
+        None
+    } else if let Some(dev) = result.clone().try_cast::<Dev>() {
+        Some(serde_json::to_value(dev)?)
+    } else {
+        Some(from_dynamic::<serde_json::Value>(&result)?)
+    };
+    if let Some(output) = &output {
+        log_output("rhai", output);
+    }
+
+    Ok(RunStatus {
+        exit_code: Some(0),
+        message: Some("success".to_string()),
+        output,
+    })
+}
+
+/// An engine with the `dev` host API registered and `import` resolving
+/// relative to the script.
+#[cfg(feature = "rhai")]
+fn engine(script_dir: PathBuf) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(FileModuleResolver::new_with_path(script_dir));
+    engine.on_debug(|message, source, pos| {
+        debug!(target: "rhai", "{}{:?} {}", source.unwrap_or_default(), pos, message)
+    });
+
+    engine
+        .register_type_with_name::<Dev>("Dev")
+        .register_get_set(
+            "version",
+            |dev: &mut Dev| dev.version.clone(),
+            |dev: &mut Dev, version: String| dev.version = version,
+        )
+        .register_get("deps", |dev: &mut Dev| to_dynamic(&dev.deps));
+
+    // Registered once per arity the function accepts, as methods on `dev`
+    for function in HOST_API {
+        for arity in function.required_params()..=function.params.len() {
+            let arg_types = std::iter::once(TypeId::of::<Dev>())
+                .chain(std::iter::repeat_n(TypeId::of::<Dynamic>(), arity))
+                .collect::<Vec<TypeId>>();
+
+            engine.register_raw_fn(function.name, arg_types, move |_, args| {
+                call(function, args)
+            });
+        }
+    }
+
+    engine
+}
+
+#[cfg(feature = "rhai")]
+fn call(function: &HostFunction, args: &mut [&mut Dynamic]) -> Result<Dynamic, Box<EvalAltResult>> {
+    let (dev, args) = args.split_first_mut().ok_or("missing dev")?;
+    let args = args
+        .iter()
+        .map(|arg| from_dynamic::<serde_json::Value>(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut dev = dev.write_lock::<Dev>().ok_or("expected dev")?;
+    let value = function
+        .call("rhai", &mut dev, args)
+        .map_err(|e| e.to_string())?;
+    to_dynamic(value)
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// Host capabilities a sandboxed script can be granted.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Process,
+    Net,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Capability::FsRead => write!(f, "fs-read"),
+            Capability::FsWrite => write!(f, "fs-write"),
+            Capability::Process => write!(f, "process"),
+            Capability::Net => write!(f, "net"),
+        }
+    }
+}
+
+/// Per-alias limits for embedded script engines, configured as
+/// `sandbox = { heap_mb = 64, timeout_ms = 5000, allow = ["fs-read"] }`.
+/// Once an alias has a sandbox it only gets the capabilities in `allow`,
+/// leaving it out grants none.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Sandbox {
+    pub heap_mb: Option<usize>,
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub allow: Vec<Capability>,
+}
+
+impl Sandbox {
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.allow.contains(&capability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_list() {
+        let sandbox: Sandbox = toml::from_str(r#"allow = ["fs-read", "net"]"#).unwrap();
+        assert!(sandbox.allows(Capability::FsRead));
+        assert!(sandbox.allows(Capability::Net));
+        assert!(!sandbox.allows(Capability::FsWrite));
+        assert!(!sandbox.allows(Capability::Process));
+    }
+
+    #[test]
+    fn denied_by_default() {
+        let sandbox: Sandbox = toml::from_str("heap_mb = 64").unwrap();
+        assert!(!sandbox.allows(Capability::FsRead));
+        assert!(!sandbox.allows(Capability::FsWrite));
+        assert!(!sandbox.allows(Capability::Process));
+        assert!(!sandbox.allows(Capability::Net));
+    }
+}
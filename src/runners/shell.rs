@@ -1,23 +1,195 @@
-use std::process::Command;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
 
+use anyhow::anyhow;
 use async_trait::async_trait;
 use log::debug;
+use tokio::process::Command;
 
 use super::{dev::Dev, language, RunError, RunStatus};
 
+/// Shells that can be named directly as a filetype.
+pub const SHELLS: &[&str] = &["sh", "bash", "zsh", "fish", "dash", "ksh", "nu", "pwsh"];
+
 #[derive(Debug, Clone)]
 pub struct ShellLanguage {
     shell: String,
+    // Prefer the script's #! line over `shell` when it has one
+    shebang: bool,
 }
 
 impl ShellLanguage {
     pub fn new(shell: &str) -> Self {
         Self {
             shell: shell.to_string(),
+            shebang: false,
+        }
+    }
+
+    /// Run scripts with the interpreter from their `#!` line, falling back
+    /// to `shell` for scripts and inline commands without one.
+    pub fn from_shebang(shell: &str) -> Self {
+        Self {
+            shell: shell.to_string(),
+            shebang: true,
+        }
+    }
+
+    pub fn set_shell(&mut self, shell: &str) {
+        self.shell = shell.to_string();
+        self.shebang = false;
+    }
+
+    pub fn get_name(&self) -> &str {
+        match self.shebang {
+            true => "shell",
+            false => &self.shell,
+        }
+    }
+
+    /// Some shells refuse to run a script without their own extension.
+    pub fn get_extension(&self) -> &str {
+        match self.shell.as_str() {
+            "fish" => ".fish",
+            "nu" => ".nu",
+            "pwsh" => ".ps1",
+            _ => ".sh",
+        }
+    }
+
+    /// The program and leading arguments to run `file` with.
+    fn interpreter(&self, file: &str) -> Result<Vec<String>, anyhow::Error> {
+        if self.shebang {
+            if let Some(interpreter) = shebang(file)? {
+                return Ok(interpreter);
+            }
+        }
+        Ok(vec![self.shell.clone()])
+    }
+}
+
+/// Run `command` to completion with the terminal handed over to it.
+pub(super) async fn run_process(
+    mut command: Command,
+    name: &str,
+) -> Result<RunStatus, anyhow::Error> {
+    #[cfg(unix)]
+    let interrupt = IgnoreInterrupt::new();
+    #[cfg(unix)]
+    {
+        let disposition = interrupt.child_disposition();
+        // SAFETY: signal(2) is async-signal-safe, nothing else runs between
+        // fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                libc::signal(libc::SIGINT, disposition);
+                Ok(())
+            });
+        }
+    }
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => anyhow!(
+            "Interpreter '{}' not found in PATH",
+            command.as_std().get_program().to_string_lossy()
+        ),
+        _ => e.into(),
+    })?;
+
+    let status = child.wait().await?;
+    #[cfg(unix)]
+    drop(interrupt);
+
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    let signal: Option<i32> = None;
+
+    match (status.code(), signal) {
+        (Some(0), _) => Ok(RunStatus {
+            exit_code: Some(0),
+            message: None,
+            output: None,
+        }),
+        (Some(code), _) => Err(anyhow!(RunError {
+            exit_code: Some(code),
+            message: Some(format!("Failed to run file: {name}, got {code}")),
+        })),
+        (None, Some(signal)) => Err(anyhow!(RunError {
+            exit_code: Some(128 + signal),
+            message: Some(format!(
+                "Failed to run file: {name}, killed by signal {signal}"
+            )),
+        })),
+        (None, None) => Err(anyhow!(RunError {
+            exit_code: None,
+            message: Some(format!("Failed to run file: {name}, process terminated")),
+        })),
+    }
+}
+
+/// Ignores SIGINT until dropped, the way `system(3)` does while it waits.
+/// The script shares the terminal's foreground process group so Ctrl-C
+/// reaches it and everything it started directly, dev only has to outlive
+/// it. Runs can overlap, so only the first guard swaps the handler out and
+/// only the last one puts it back, later steps can still be interrupted.
+#[cfg(unix)]
+struct IgnoreInterrupt(libc::sighandler_t);
+
+// How many guards are alive and the handler the first one replaced
+#[cfg(unix)]
+static INTERRUPT: std::sync::Mutex<(usize, libc::sighandler_t)> =
+    std::sync::Mutex::new((0, libc::SIG_DFL));
+
+#[cfg(unix)]
+impl IgnoreInterrupt {
+    fn new() -> Self {
+        let mut interrupt = INTERRUPT.lock().unwrap_or_else(|e| e.into_inner());
+        if interrupt.0 == 0 {
+            // SAFETY: only swaps the disposition of SIGINT
+            interrupt.1 = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+        }
+        interrupt.0 += 1;
+        Self(interrupt.1)
+    }
+
+    /// What SIGINT should be in a child, exec resets handlers to the
+    /// default but keeps SIG_IGN, which only dev itself should have.
+    fn child_disposition(&self) -> libc::sighandler_t {
+        match self.0 {
+            libc::SIG_IGN => libc::SIG_IGN,
+            _ => libc::SIG_DFL,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for IgnoreInterrupt {
+    fn drop(&mut self) {
+        let mut interrupt = INTERRUPT.lock().unwrap_or_else(|e| e.into_inner());
+        interrupt.0 -= 1;
+        if interrupt.0 == 0 {
+            // SAFETY: restores the disposition the first guard replaced
+            unsafe { libc::signal(libc::SIGINT, interrupt.1) };
         }
     }
 }
 
+/// Split the `#!` line of `file` into the interpreter and its arguments.
+fn shebang(file: &str) -> Result<Option<Vec<String>>, io::Error> {
+    let mut line = String::new();
+    BufReader::new(File::open(file)?).read_line(&mut line)?;
+
+    let interpreter = line
+        .strip_prefix("#!")
+        .map(|line| line.split_whitespace().map(String::from).collect())
+        .filter(|interpreter: &Vec<String>| !interpreter.is_empty());
+
+    Ok(interpreter)
+}
+
 impl Default for ShellLanguage {
     fn default() -> Self {
         Self::new("bash")
@@ -32,57 +204,114 @@ impl language::LanguageFunctions for ShellLanguage {
         file: &str,
         args: Vec<&str>,
     ) -> Result<RunStatus, anyhow::Error> {
-        let cmd = format!("{} {}", file, args.join(" "));
+        let interpreter = self.interpreter(file)?;
 
         debug!(
-            "running cmd: {} in shell: {} with envs: {}",
-            cmd,
-            self.shell,
+            "running file: {} {} in shell: {} with envs: {}",
+            file,
+            args.join(" "),
+            interpreter.join(" "),
             dev.get_env()
                 .iter()
-                .fold(
-                    "".to_string(),
-                    |acc, e| acc + &format!("{}={} ", e.0, e.1)
-                )
+                .map(|(key, value)| format!("{key}={value} "))
+                .collect::<String>()
         );
 
-        let mut child = Command::new(self.shell.as_str())
-            .arg("-c")
-            .arg(cmd)
-            .envs(dev.get_env().clone())
-            .spawn()
-            .expect("failed to execute child");
-
-        match child.wait()?.code() {
-            Some(code) => {
-                if code != 0 {
-                    Err(anyhow::anyhow!(RunError {
-                        exit_code: Some(code),
-                        message: Some(format!("Failed to run file: {file}, got {code}")),
-                    }))
-                } else {
-                    Ok(RunStatus {
-                        exit_code: Some(code),
-                        message: None,
-                    })
-                }
-            }
-            None => Err(anyhow::anyhow!(RunError {
-                exit_code: None,
-                message: Some(format!("Failed to run file: {file}, process terminated")),
-            })),
-        }
+        // Arguments go to the shell as their own argv entries so they reach
+        // the script as $1..$n untouched, without being split or expanded
+        let mut command = Command::new(&interpreter[0]);
+        command
+            .args(&interpreter[1..])
+            .arg(file)
+            .args(&args)
+            .envs(dev.get_env());
+
+        run_process(command, file).await
     }
 
     async fn load_file(&self, _file: &str) -> Result<(), anyhow::Error> {
         todo!()
     }
 
-    async fn run_shell(
-        &self,
-        _command: &str,
-        _args: Vec<&str>,
-    ) -> Result<RunStatus, anyhow::Error> {
-        todo!()
+    async fn run_shell(&self, command: &str, args: Vec<&str>) -> Result<RunStatus, anyhow::Error> {
+        debug!("running command: {} in shell: {}", command, self.shell);
+
+        // With -c the first argument after the command becomes $0, pass the
+        // shell name there so the rest line up as $1..$n
+        let mut shell = Command::new(self.shell.as_str());
+        shell
+            .arg("-c")
+            .arg(command)
+            .arg(self.shell.as_str())
+            .args(&args);
+
+        run_process(shell, command).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebang_with_arguments() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        std::fs::write(path, "#!/usr/bin/env bash -e\necho hi\n").unwrap();
+
+        let interpreter = shebang(path).unwrap();
+        assert_eq!(
+            interpreter,
+            Some(vec!["/usr/bin/env".into(), "bash".into(), "-e".into()])
+        );
+
+        std::fs::write(path, "echo hi\n").unwrap();
+        assert_eq!(shebang(path).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    fn interrupt_handler() -> libc::sighandler_t {
+        // SAFETY: only reads the current disposition
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGINT, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn overlapping_runs_keep_interrupts() {
+        use std::time::Duration;
+        use tokio::time::sleep;
+
+        let script = |script: &str| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        };
+        let before = interrupt_handler();
+
+        // The first run finishes while the second is still going, which has
+        // to keep ignoring SIGINT but not pass that on to its child
+        let first = run_process(script("sleep 0.1"), "first");
+        let second = async {
+            sleep(Duration::from_millis(50)).await;
+            run_process(script("sleep 0.3; kill -INT $$; exit 3"), "second").await
+        };
+        let check = async {
+            sleep(Duration::from_millis(200)).await;
+            interrupt_handler()
+        };
+        let (first, second, during) = tokio::join!(first, second, check);
+
+        assert!(first.is_ok());
+        let error = second.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RunError>().unwrap().exit_code,
+            Some(130)
+        );
+        assert_eq!(during, libc::SIG_IGN);
+        assert_eq!(interrupt_handler(), before);
     }
 }
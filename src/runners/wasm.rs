@@ -0,0 +1,370 @@
+#![allow(unused_imports)]
+
+use std::env;
+#[cfg(feature = "wasm")]
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::debug;
+
+#[cfg(feature = "wasm")]
+use wasmtime::{Config, Engine, Linker, Module, ResourceLimiter, Store, Trap};
+#[cfg(feature = "wasm")]
+use wasmtime_wasi::{preview1, preview1::WasiP1Ctx, DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+use super::{dev::Dev, language, Capability, RunError, RunStatus, Sandbox};
+
+/// Runs WASI (preview 1) modules, the module only sees its arguments, the
+/// alias' `environment` and the working directory preopened as `.`. The
+/// directory is read only unless the alias' sandbox allows `fs-write`, a
+/// sandbox allowing neither `fs-read` nor `fs-write` gets no directory.
+#[derive(Debug, Clone)]
+pub struct WasmLanguage {
+    sandbox: Option<Sandbox>,
+    environment: Vec<String>,
+}
+
+impl WasmLanguage {
+    pub fn new() -> Self {
+        Self {
+            sandbox: None,
+            environment: vec![],
+        }
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(sandbox);
+    }
+
+    /// Names of the variables the module is handed, the values come from
+    /// `Dev` so they are already rendered.
+    pub fn set_environment(&mut self, names: Vec<String>) {
+        self.environment = names;
+    }
+}
+
+impl Default for WasmLanguage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl language::LanguageFunctions for WasmLanguage {
+    #[allow(unused_variables)]
+    async fn run_file(
+        &self,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        #[cfg(not(feature = "wasm"))]
+        return Err(anyhow!("wasm support is not enabled"));
+
+        #[cfg(feature = "wasm")]
+        return self.run_file(dev, file, args).await;
+    }
+
+    async fn load_file(&self, _file: &str) -> Result<(), anyhow::Error> {
+        Err(anyhow!("loading files is not supported for wasm"))
+    }
+
+    async fn run_shell(
+        &self,
+        _command: &str,
+        _args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        Err(anyhow!("wasm has no interactive shell"))
+    }
+}
+
+/// Store data for a run, the WASI context plus the memory cap taken from
+/// the alias' sandbox.
+#[cfg(feature = "wasm")]
+struct State {
+    wasi: WasiP1Ctx,
+    memory: MemoryLimit,
+}
+
+/// Caps linear memory at the sandbox's `heap_mb`. A refused grow traps the
+/// module, `exceeded` tells the runner that was the reason.
+#[cfg(feature = "wasm")]
+struct MemoryLimit {
+    bytes: Option<usize>,
+    exceeded: bool,
+}
+
+#[cfg(feature = "wasm")]
+impl ResourceLimiter for MemoryLimit {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        if self.bytes.is_some_and(|bytes| desired > bytes) {
+            self.exceeded = true;
+            return Err(anyhow!("memory limit exceeded"));
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl WasmLanguage {
+    async fn run_file(
+        &self,
+        dev: Dev,
+        file: &str,
+        args: Vec<&str>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        let sandbox = self.sandbox.clone().unwrap_or_default();
+
+        let mut config = Config::new();
+        config
+            .async_support(true)
+            .epoch_interruption(sandbox.timeout_ms.is_some());
+        let engine = Engine::new(&config)?;
+
+        let mut linker: Linker<State> = Linker::new(&engine);
+        preview1::add_to_linker_async(&mut linker, |state| &mut state.wasi)?;
+
+        let argv = std::iter::once(file)
+            .chain(args.iter().copied())
+            .collect::<Vec<&str>>();
+        let env = dev
+            .get_env()
+            .into_iter()
+            .filter(|(key, _)| self.environment.contains(key))
+            .collect::<Vec<(String, String)>>();
+
+        let mut builder = WasiCtxBuilder::new();
+        builder.inherit_stdio().args(&argv).envs(&env);
+
+        let (read, write) = match &self.sandbox {
+            Some(sandbox) => (
+                sandbox.allows(Capability::FsRead),
+                sandbox.allows(Capability::FsWrite),
+            ),
+            None => (true, false),
+        };
+        if write {
+            builder.preopened_dir(env::current_dir()?, ".", DirPerms::all(), FilePerms::all())?;
+        } else if read {
+            builder.preopened_dir(env::current_dir()?, ".", DirPerms::READ, FilePerms::READ)?;
+        }
+
+        let mut store = Store::new(
+            &engine,
+            State {
+                wasi: builder.build_p1(),
+                memory: MemoryLimit {
+                    bytes: sandbox.heap_mb.map(|heap_mb| heap_mb * 1024 * 1024),
+                    exceeded: false,
+                },
+            },
+        );
+        store.limiter(|state| &mut state.memory);
+
+        // Module::from_file takes the text format as well, which is handy
+        // for inline commands. Compiling can take a while for big modules,
+        // keep it off the async workers
+        let module = {
+            let engine = engine.clone();
+            let file = file.to_string();
+            tokio::task::spawn_blocking(move || Module::from_file(&engine, file)).await??
+        };
+
+        // The deadline is a single epoch tick, the watchdog bumps the epoch
+        // once the timeout is up. Dropping the sender wakes it when the
+        // module finishes first
+        let (_watchdog, receiver) = mpsc::channel::<()>();
+        if let Some(timeout_ms) = sandbox.timeout_ms {
+            store.set_epoch_deadline(1);
+            store.epoch_deadline_trap();
+
+            let engine = engine.clone();
+            thread::spawn(move || {
+                let timeout = Duration::from_millis(timeout_ms);
+                if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+                    engine.increment_epoch();
+                }
+            });
+        }
+
+        debug!(target: "wasm", "running {} with args {:?}", file, args);
+
+        let result = async {
+            let instance = linker.instantiate_async(&mut store, &module).await?;
+            let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+            start.call_async(&mut store, ()).await
+        }
+        .await;
+
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(e) => {
+                if let Some(exit) = e.downcast_ref::<I32Exit>() {
+                    exit.0
+                } else if store.data().memory.exceeded {
+                    return Err(anyhow!(RunError {
+                        exit_code: None,
+                        message: Some(format!(
+                            "Module exceeded the heap limit of {} MB",
+                            sandbox.heap_mb.unwrap_or_default()
+                        )),
+                    }));
+                } else if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) {
+                    return Err(anyhow!(RunError {
+                        exit_code: None,
+                        message: Some(format!(
+                            "Module did not finish within {} ms",
+                            sandbox.timeout_ms.unwrap_or_default()
+                        )),
+                    }));
+                } else {
+                    return Err(e.context(format!("Failed to run file: {file}")));
+                }
+            }
+        };
+
+        match exit_code {
+            0 => Ok(RunStatus {
+                exit_code: Some(0),
+                message: None,
+                output: None,
+            }),
+            code => Err(anyhow!(RunError {
+                exit_code: Some(code),
+                message: Some(format!("Failed to run file: {file}, got {code}")),
+            })),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::config::Config;
+
+    const EXIT_ARGC: &str = r#"(module
+        (import "wasi_snapshot_preview1" "args_sizes_get"
+            (func $args_sizes_get (param i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+            (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+            (call $proc_exit (i32.sub (i32.load (i32.const 0)) (i32.const 1)))))"#;
+
+    // Opens Cargo.toml in the preopened directory with the given rights and
+    // exits with the errno, opening never creates or truncates the file
+    fn open_cargo_toml(rights: u64) -> String {
+        format!(
+            r#"(module
+            (import "wasi_snapshot_preview1" "path_open"
+                (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "Cargo.toml")
+            (func (export "_start")
+                (call $proc_exit (call $path_open
+                    (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 10) (i32.const 0)
+                    (i64.const {rights}) (i64.const 0) (i32.const 0) (i32.const 8)))))"#
+        )
+    }
+
+    const FD_READ: u64 = 1 << 1;
+    const FD_WRITE: u64 = 1 << 6;
+
+    async fn run(
+        source: &str,
+        args: Vec<&str>,
+        sandbox: Option<Sandbox>,
+    ) -> Result<RunStatus, anyhow::Error> {
+        let mut file = tempfile::Builder::new().suffix(".wat").tempfile()?;
+        file.write_all(source.as_bytes())?;
+
+        let mut language = WasmLanguage::new();
+        if let Some(sandbox) = sandbox {
+            language.set_sandbox(sandbox);
+        }
+        let dev = Dev::new(&Config::default());
+        let path = file.path().to_string_lossy().into_owned();
+        language.run_file(dev, &path, args).await
+    }
+
+    fn exit_code(result: Result<RunStatus, anyhow::Error>) -> Option<i32> {
+        match result {
+            Ok(status) => status.exit_code,
+            Err(error) => error.downcast_ref::<RunError>().unwrap().exit_code,
+        }
+    }
+
+    fn sandbox(source: &str) -> Option<Sandbox> {
+        Some(toml::from_str(source).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exit_code_and_args() {
+        assert_eq!(exit_code(run(EXIT_ARGC, vec![], None).await), Some(0));
+        assert_eq!(
+            exit_code(run(EXIT_ARGC, vec!["a", "b"], None).await),
+            Some(2)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn preopened_directory() {
+        let read = open_cargo_toml(FD_READ);
+        let write = open_cargo_toml(FD_WRITE);
+
+        assert_eq!(exit_code(run(&read, vec![], None).await), Some(0));
+        assert_ne!(exit_code(run(&write, vec![], None).await), Some(0));
+
+        // Without fs-read or fs-write there is no fd 3 at all
+        assert_eq!(exit_code(run(&read, vec![], sandbox("")).await), Some(8));
+
+        let allowed = sandbox(r#"allow = ["fs-write"]"#);
+        assert_eq!(exit_code(run(&write, vec![], allowed).await), Some(0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn limits() {
+        let spin = r#"(module (func (export "_start") (loop $spin (br $spin))))"#;
+        let error = run(spin, vec![], sandbox("timeout_ms = 100"))
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<RunError>().unwrap();
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Module did not finish within 100 ms")
+        );
+
+        let grow = r#"(module
+            (memory 1)
+            (func (export "_start") (drop (memory.grow (i32.const 100)))))"#;
+        let error = run(grow, vec![], sandbox("heap_mb = 1")).await.unwrap_err();
+        let error = error.downcast_ref::<RunError>().unwrap();
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Module exceeded the heap limit of 1 MB")
+        );
+        assert!(run(grow, vec![], None).await.is_ok());
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
+    env,
     fs::File,
     io::{self, Write},
+    path::{Path, PathBuf},
 };
 
 pub fn write_tmp_file<P>(path: P, contents: &str, set_excecute: bool) -> Result<(), io::Error>
@@ -19,3 +21,12 @@ where
 
     Ok(())
 }
+
+/// The directory `file` lives in, or the working directory when it's a bare
+/// file name.
+pub fn script_dir(file: &str) -> Result<PathBuf, io::Error> {
+    match Path::new(file).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize(),
+        _ => env::current_dir(),
+    }
+}
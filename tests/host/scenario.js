@@ -0,0 +1,45 @@
+// Host API conformance, every scenario.* does the same steps and throws as
+// soon as something doesn't match
+import * as dev from 'dev'
+
+function check(ok, what) {
+  if (!ok) {
+    throw new Error(`host api: ${what}`);
+  }
+}
+
+check(typeof dev.getVersion() === "string", "getVersion");
+dev.setVersion("9.9.9");
+check(dev.getVersion() === "9.9.9", "setVersion");
+
+dev.setDir("/tmp/host");
+check(dev.getDir() === "/tmp/host", "setDir");
+check(dev.getWorkDir() !== "", "getWorkDir");
+
+dev.setEnv("HOST_KEY", "value");
+check(dev.getEnv("HOST_KEY") === "value", "setEnv");
+check(dev.getEnv().HOST_KEY === "value", "getEnv");
+check(dev.getEnv("HOST_MISSING") == null, "getEnv missing key");
+
+dev.addStep("build");
+const steps = dev.getSteps();
+check(steps.length === 1 && steps[0] === "build", "addStep");
+check(Object.keys(dev.getDeps()).length === 0 && Object.keys(dev.deps).length === 0, "getDeps");
+
+const repos = dev.getRepos();
+check(repos.length === 1 && repos[0].name === "alpha", "getRepos");
+check(dev.getRepo("alpha").path === "tests/alpha", "getRepo");
+check(dev.getRepo("missing") == null, "getRepo missing");
+
+const alias = dev.getAlias("noop");
+check(alias.filetype === "sh" && alias.command === "true", "getAlias");
+await dev.run("noop");
+
+const output = await dev.exec("echo", ["hello"]);
+check(output.code === 0 && output.stdout === "hello\n", "exec");
+
+const yaml = dev.getEnv("HOST_YAML");
+await dev.writeYaml(yaml, { name: "dev", count: 2 });
+check((await dev.readYaml(yaml)).count === 2, "yaml");
+
+dev.log("host api conformance passed", "debug");
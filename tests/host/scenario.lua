@@ -0,0 +1,45 @@
+-- Host API conformance, every scenario.* does the same steps and errors as
+-- soon as something doesn't match
+local dev = require 'dev'
+
+local function check(ok, what)
+  if not ok then
+    error("host api: " .. what)
+  end
+end
+
+check(type(dev:get_version()) == "string", "get_version")
+dev:set_version("9.9.9")
+check(dev:get_version() == "9.9.9", "set_version")
+
+dev:set_dir("/tmp/host")
+check(dev:get_dir() == "/tmp/host", "set_dir")
+check(dev:get_work_dir() ~= "", "get_work_dir")
+
+dev:set_env("HOST_KEY", "value")
+check(dev:get_env("HOST_KEY") == "value", "set_env")
+check(dev:get_env().HOST_KEY == "value", "get_env")
+check(dev:get_env("HOST_MISSING") == nil, "get_env missing key")
+
+dev:add_step("build")
+local steps = dev:get_steps()
+check(#steps == 1 and steps[1] == "build", "add_step")
+check(next(dev:get_deps()) == nil and next(dev.deps) == nil, "get_deps")
+
+local repos = dev:get_repos()
+check(#repos == 1 and repos[1].name == "alpha", "get_repos")
+check(dev:get_repo("alpha").path == "tests/alpha", "get_repo")
+check(dev:get_repo("missing") == nil, "get_repo missing")
+
+local alias = dev:get_alias("noop")
+check(alias.filetype == "sh" and alias.command == "true", "get_alias")
+dev:run("noop")
+
+local output = dev:exec("echo", { "hello" })
+check(output.code == 0 and output.stdout == "hello\n", "exec")
+
+local yaml = dev:get_env("HOST_YAML")
+dev:write_yaml(yaml, { name = "dev", count = 2 })
+check(dev:read_yaml(yaml).count == 2, "yaml")
+
+dev:log("host api conformance passed", "debug")
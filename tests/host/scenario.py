@@ -0,0 +1,44 @@
+# Host API conformance, every scenario.* does the same steps and raises as
+# soon as something doesn't match
+import dev
+
+
+def check(ok, what):
+    if not ok:
+        raise AssertionError(f"host api: {what}")
+
+
+check(isinstance(dev.get_version(), str), "get_version")
+dev.set_version("9.9.9")
+check(dev.get_version() == "9.9.9", "set_version")
+
+dev.set_dir("/tmp/host")
+check(dev.get_dir() == "/tmp/host", "set_dir")
+check(dev.get_work_dir() != "", "get_work_dir")
+
+dev.set_env("HOST_KEY", "value")
+check(dev.get_env("HOST_KEY") == "value", "set_env")
+check(dev.get_env()["HOST_KEY"] == "value", "get_env")
+check(dev.get_env("HOST_MISSING") is None, "get_env missing key")
+
+dev.add_step("build")
+check(dev.get_steps() == ["build"], "add_step")
+check(dev.get_deps() == {} and dev.deps == {}, "get_deps")
+
+repos = dev.get_repos()
+check(len(repos) == 1 and repos[0]["name"] == "alpha", "get_repos")
+check(dev.get_repo("alpha")["path"] == "tests/alpha", "get_repo")
+check(dev.get_repo("missing") is None, "get_repo missing")
+
+alias = dev.get_alias("noop")
+check(alias["filetype"] == "sh" and alias["command"] == "true", "get_alias")
+dev.run("noop")
+
+output = dev.exec("echo", args=["hello"])
+check(output["code"] == 0 and output["stdout"] == "hello\n", "exec")
+
+yaml = dev.get_env("HOST_YAML")
+dev.write_yaml(yaml, {"name": "dev", "count": 2})
+check(dev.read_yaml(yaml)["count"] == 2, "yaml")
+
+dev.log("host api conformance passed", "debug")